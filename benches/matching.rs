@@ -0,0 +1,105 @@
+use codecrafters_grep::regex_matcher::Regex;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+// Input sizes in bytes, to show how matching time grows with the input
+const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+
+// Filler text that none of the patterns match, followed by a match at the end
+fn input(size: usize, tail: &str) -> String {
+    let mut text = "the quick brown fox jumps over the lazy dog "
+        .chars()
+        .cycle()
+        .take(size)
+        .collect::<String>();
+    text.push_str(tail);
+    text
+}
+
+fn bench_pattern(c: &mut Criterion, name: &str, pattern: &str, tail: &str) {
+    let regex = Regex::new(pattern).unwrap();
+    let mut group = c.benchmark_group(name);
+    for size in SIZES {
+        let text = input(size, tail);
+        group.throughput(Throughput::Bytes(text.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &text, |b, text| {
+            b.iter(|| regex.is_match(black_box(text)))
+        });
+    }
+    group.finish();
+}
+
+fn literal(c: &mut Criterion) {
+    bench_pattern(c, "literal", "needle", "needle");
+}
+
+fn quantifier(c: &mut Criterion) {
+    bench_pattern(c, "digits", r"\d+", "12345");
+    bench_pattern(c, "dot_star", ".*foo", "foo");
+    // A leading `.*` is only tried from the start of each line
+    bench_pattern(c, "dot_star_class", ".*[x]", "x");
+}
+
+// Start positions without a `#` are skipped rather than tried
+fn first_char(c: &mut Criterion) {
+    bench_pattern(c, "first_char", r"#\d+", "#12345");
+}
+
+fn alternation(c: &mut Criterion) {
+    bench_pattern(c, "alternation", "(a|b)+c", "ababc");
+}
+
+// The branches share the prefix `inter`, which is matched once for all of them
+fn shared_prefix(c: &mut Criterion) {
+    let pattern = "interest|internal|internet|interval|interview|interior|interim";
+    bench_pattern(c, "shared_prefix", pattern, "interview");
+}
+
+// Groups only record their spans when captures are asked for, so `is_match`
+// should beat `captures` on the same pattern
+fn groups(c: &mut Criterion) {
+    let regex = Regex::new(r"(\w+)@(\w+)").unwrap();
+    let text = input(10_000, "user@example");
+    let mut group = c.benchmark_group("groups");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.bench_function("is_match", |b| b.iter(|| regex.is_match(black_box(&text))));
+    group.bench_function("captures", |b| b.iter(|| regex.captures(black_box(&text))));
+    group.finish();
+}
+
+// Without groups there are no spans to record, so `captures` should cost
+// about as much as `is_match`
+fn captures_no_groups(c: &mut Criterion) {
+    let regex = Regex::new(r"\w+@\w+").unwrap();
+    let text = input(10_000, "user@example");
+    let mut group = c.benchmark_group("captures_no_groups");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.bench_function("is_match", |b| b.iter(|| regex.is_match(black_box(&text))));
+    group.bench_function("captures", |b| b.iter(|| regex.captures(black_box(&text))));
+    group.finish();
+}
+
+// A lone literal is searched for directly in the bytes, while other patterns
+// are matched against a copy of them as a string
+fn bytes(c: &mut Criterion) {
+    let literal = Regex::new("needle").unwrap();
+    let class = Regex::new("needl[e]").unwrap();
+    let text = input(10_000, "needle").into_bytes();
+    let mut group = c.benchmark_group("bytes");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.bench_function("literal", |b| b.iter(|| literal.is_match_bytes(black_box(&text))));
+    group.bench_function("class", |b| b.iter(|| class.is_match_bytes(black_box(&text))));
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    literal,
+    quantifier,
+    first_char,
+    alternation,
+    shared_prefix,
+    groups,
+    captures_no_groups,
+    bytes
+);
+criterion_main!(benches);
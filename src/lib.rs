@@ -0,0 +1,2 @@
+pub mod regex_cache;
+pub mod regex_matcher;
@@ -1,30 +1,913 @@
+use std::collections::VecDeque;
 use std::env;
-use std::io;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, IsTerminal};
+use std::path::Path;
 use std::process;
 
-mod regex_matcher;
+use codecrafters_grep::regex_matcher::{
+    basic_to_extended, MatchError, MatchSemantics, Regex, RegexOptions,
+};
 
-use regex_matcher::match_pattern as basic_match_pattern;
+// ANSI escapes wrapped around matched text when highlighting
+const MATCH_COLOR: &str = "\x1b[01;31m";
+const RESET_COLOR: &str = "\x1b[m";
+
+// Pattern attempts allowed from each start position in a line before the line
+// is skipped as too costly to search
+const STEP_LIMIT: usize = 10_000_000;
+
+// Describes the argument of -A, -B and -C in errors
+const CONTEXT: &str = "context length argument";
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    
-    if args.len() < 3 {
-        eprintln!("Usage: {} [-E|-e] <pattern>", args[0]);
-        process::exit(1);
+    let mut args = split_short_options(env::args()).into_iter();
+    let program = args.next().unwrap_or_default();
+
+    let mut encoding = None;
+    let mut no_messages = false;
+    let mut recursive = false;
+    let mut only_matching = false;
+    let mut byte_offset = false;
+    let mut semantics = MatchSemantics::LeftmostFirst;
+    let mut before_context = 0;
+    let mut after_context = 0;
+    let mut max_count = None;
+    let mut list_files = None;
+    let mut quiet = false;
+    let mut invert = false;
+    let mut count_only = false;
+    let mut options = RegexOptions {
+        step_limit: Some(STEP_LIMIT),
+        ..RegexOptions::default()
+    };
+    let mut color_when = "never".to_string();
+    let mut extended = false;
+    let mut patterns = Vec::new();
+    let mut pattern_files = Vec::new();
+    let mut sed = None;
+    let mut include = Vec::new();
+    let mut exclude = Vec::new();
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        if let Some(label) = arg.strip_prefix("--encoding=") {
+            encoding = Some(label.to_string());
+            continue;
+        }
+        if let Some(when) = arg
+            .strip_prefix("--color=")
+            .or_else(|| arg.strip_prefix("--colour="))
+        {
+            color_when = when.to_string();
+            continue;
+        }
+        if let Some(glob) = arg.strip_prefix("--include=") {
+            include.push(glob_regex(&program, glob));
+            continue;
+        }
+        if let Some(glob) = arg.strip_prefix("--exclude=") {
+            exclude.push(glob_regex(&program, glob));
+            continue;
+        }
+        match arg.as_str() {
+            "-E" | "--extended-regexp" => extended = true,
+            "-G" | "--basic-regexp" => extended = false,
+            "-e" | "--regexp" => match args.next() {
+                Some(pattern) => patterns.push(pattern),
+                None => usage(&program),
+            },
+            "-s" | "--no-messages" => no_messages = true,
+            "-q" | "--quiet" | "--silent" => quiet = true,
+            "-v" | "--invert-match" => invert = true,
+            "-c" | "--count" => count_only = true,
+            "-r" | "--recursive" => recursive = true,
+            "-o" | "--only-matching" => only_matching = true,
+            "-b" | "--byte-offset" => byte_offset = true,
+            "-w" | "--word-regexp" => options.word = true,
+            "-x" | "--line-regexp" => options.line = true,
+            "-i" | "--ignore-case" => options.case_insensitive = true,
+            "-F" | "--fixed-strings" => options.fixed = true,
+            "--posix" => semantics = MatchSemantics::LeftmostLongest,
+            "--color" | "--colour" => color_when = "auto".to_string(),
+            "-A" | "--after-context" => after_context = count(&program, args.next(), CONTEXT),
+            "-B" | "--before-context" => before_context = count(&program, args.next(), CONTEXT),
+            "-C" | "--context" => {
+                after_context = count(&program, args.next(), CONTEXT);
+                before_context = after_context;
+            }
+            "-l" | "--files-with-matches" => list_files = Some(ListFiles::Matching),
+            "-L" | "--files-without-match" => list_files = Some(ListFiles::NonMatching),
+            "-m" | "--max-count" => max_count = Some(count(&program, args.next(), "max count")),
+            "-f" => match args.next() {
+                Some(path) => pattern_files.push(path),
+                None => usage(&program),
+            },
+            "--sed" => match args.next() {
+                Some(command) => sed = Some(command),
+                None => usage(&program),
+            },
+            // Everything after `--` is a pattern or file name, even if it
+            // starts with `-`
+            "--" => positional.extend(args.by_ref()),
+            // A lone `-` is still taken as a file name
+            _ if arg.starts_with('-') && arg != "-" => {
+                eprintln!("{}: unknown option '{}'", program, arg);
+                usage(&program);
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let color = match color_when.as_str() {
+        "always" => true,
+        "never" => false,
+        "auto" => io::stdout().is_terminal(),
+        _ => {
+            eprintln!("invalid argument '{}' for --color", color_when);
+            eprintln!("Valid arguments are 'always', 'never' and 'auto'");
+            process::exit(2);
+        }
+    };
+
+    // An unknown label is a usage error, not a file that fails to decode
+    let encoding = match encoding.as_deref().map(encoding_for).transpose() {
+        Ok(encoding) => encoding,
+        Err(err) => {
+            eprintln!("{}: {}", program, err);
+            process::exit(2);
+        }
+    };
+
+    // With --sed every line is printed, edited by the command, instead of
+    // searching
+    if let Some(command) = sed {
+        let (pattern, replacement, global) = match parse_sed(&command) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                eprintln!("{}: {}", program, err);
+                process::exit(2);
+            }
+        };
+        let pattern = if extended { pattern } else { basic_to_extended(&pattern) };
+        let regex = match Regex::with_options(&pattern, options) {
+            Ok(regex) => regex,
+            Err(err) => {
+                eprintln!("{}: {}", program, err);
+                process::exit(2);
+            }
+        };
+        process::exit(run_sed(&regex, &replacement, global, &positional, no_messages));
+    }
+
+    // Patterns come from -e and the -f files if any were given, otherwise from
+    // the first positional argument
+    for path in &pattern_files {
+        match fs::read_to_string(path) {
+            Ok(contents) => patterns.extend(
+                contents
+                    .lines()
+                    .filter(|line| !line.is_empty())
+                    .map(str::to_string),
+            ),
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                process::exit(2);
+            }
+        }
+    }
+    if patterns.is_empty() && pattern_files.is_empty() {
+        if positional.is_empty() {
+            usage(&program);
+        }
+        patterns.push(positional.remove(0));
+    }
+
+    let compiled: Result<Vec<Regex>, _> = patterns
+        .iter()
+        .map(|pattern| {
+            if extended || options.fixed {
+                Regex::with_options(pattern, options)
+            } else {
+                Regex::with_options(&basic_to_extended(pattern), options)
+            }
+        })
+        .collect();
+    let regexes = match compiled {
+        Ok(regexes) => regexes,
+        Err(err) => {
+            eprintln!("{}: {}", program, err);
+            process::exit(2);
+        }
+    };
+
+    if recursive && positional.is_empty() {
+        positional.push(".".to_string());
+    }
+    // Unreadable files and directories don't stop the search, but make the
+    // exit status 2 whether or not anything matched
+    let mut had_error = false;
+    let mut files = Vec::new();
+    let filter = FileFilter { include, exclude };
+    for path in &positional {
+        if recursive && Path::new(path).is_dir() {
+            had_error |= !collect_files(Path::new(path), &filter, &mut files, no_messages);
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    // Only the matched parts are printed with -o, never the lines around them
+    if only_matching {
+        before_context = 0;
+        after_context = 0;
+    }
+    // Of the options that replace the printed lines, -q wins over -l and -L,
+    // which win over -c. All of them count the lines -v selects, up to the
+    // -m limit. Listing file names needs each input read only as far as its
+    // first selected line.
+    if list_files.is_some() || count_only {
+        before_context = 0;
+        after_context = 0;
+    }
+    if list_files.is_some() {
+        max_count = Some(max_count.map_or(1, |max_count: usize| max_count.min(1)));
+    }
+    let mut output = Output {
+        color,
+        only_matching,
+        byte_offset,
+        semantics,
+        with_filename: false,
+        before_context,
+        after_context,
+        max_count,
+        list_files,
+        quiet,
+        invert,
+        count_only,
+    };
+    let mut scan = Scan::default();
+    let result = if positional.is_empty() {
+        let stdin = io::stdin().lock();
+        match print_stream_matches(&regexes, stdin, "(standard input)", &output, &mut scan) {
+            Ok(matched) => {
+                print_summary("(standard input)", &scan, &output);
+                matched
+            }
+            Err(err) => {
+                eprintln!("(standard input): {}", err);
+                process::exit(2);
+            }
+        }
+    } else {
+        output.with_filename = recursive || files.len() > 1;
+        let mut matched = false;
+        for path in &files {
+            let bytes = match fs::read(path) {
+                Ok(bytes) => bytes,
+                Err(err) => {
+                    if !no_messages {
+                        eprintln!("{}: {}", path, err);
+                    }
+                    had_error = true;
+                    continue;
+                }
+            };
+            let text = match decode(bytes, encoding) {
+                Ok(text) => text,
+                Err(err) => {
+                    // Files found while recursing that don't decode are
+                    // treated as binary and skipped quietly
+                    if !recursive {
+                        if !no_messages {
+                            eprintln!("{}: {}", path, err);
+                        }
+                        had_error = true;
+                    }
+                    continue;
+                }
+            };
+            scan.next_input();
+            matched |= print_matches(&regexes, &text, path, &output, &mut scan);
+            print_summary(path, &scan, &output);
+        }
+        matched
+    };
+
+    if had_error {
+        process::exit(2);
+    }
+    process::exit(if result { 0 } else { 1 });
+}
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} [-EFGLbciloqrsvwx] [-A|-B|-C|-m <num>] [--color[=WHEN]] [--encoding=<label>] [--posix] [--include=|--exclude=<glob>] <pattern> [file...]",
+        program
+    );
+    eprintln!(
+        "       {} [-EFGLbciloqrsvwx] [-A|-B|-C|-m <num>] [--color[=WHEN]] [--encoding=<label>] [--posix] [-e <pattern>]... [-f <pattern-file>]... [file...]",
+        program
+    );
+    eprintln!("       {} [-EFGiw] --sed s/<pattern>/<replacement>/[g] [file...]", program);
+    process::exit(2);
+}
+
+// Options that take a value, as the next argument or, for short options, the
+// rest of the same argument
+const SHORT_OPTIONS_WITH_VALUES: &str = "ABCefm";
+const LONG_OPTIONS_WITH_VALUES: [&str; 6] =
+    ["--regexp", "--after-context", "--before-context", "--context", "--max-count", "--sed"];
+
+// Splits bundled short options such as `-cv` into `-c -v`, and a value attached
+// to an option that takes one, as in `-m2`, into its own argument. Arguments
+// after `--` are left as they are.
+fn split_short_options(mut args: impl Iterator<Item = String>) -> Vec<String> {
+    let mut split = Vec::new();
+    let mut is_value = false;
+    while let Some(arg) = args.next() {
+        // An option's value is taken as it is, even if it starts with `-`
+        if is_value {
+            is_value = false;
+            split.push(arg);
+            continue;
+        }
+        if arg == "--" {
+            split.push(arg);
+            split.extend(args);
+            break;
+        }
+        let bundle = match arg.strip_prefix('-') {
+            Some(bundle) if !bundle.is_empty() && !bundle.starts_with('-') => bundle,
+            _ => {
+                is_value = LONG_OPTIONS_WITH_VALUES.contains(&arg.as_str());
+                split.push(arg);
+                continue;
+            }
+        };
+        for (i, option) in bundle.char_indices() {
+            split.push(format!("-{}", option));
+            if SHORT_OPTIONS_WITH_VALUES.contains(option) {
+                let value = &bundle[i + option.len_utf8()..];
+                if value.is_empty() {
+                    is_value = true;
+                } else {
+                    split.push(value.to_string());
+                }
+                break;
+            }
+        }
+    }
+    split
+}
+
+// Parses the line count given to an option, described by `what` in errors
+fn count(program: &str, arg: Option<String>, what: &str) -> usize {
+    let Some(arg) = arg else { usage(program) };
+    match arg.parse() {
+        Ok(count) => count,
+        Err(_) => {
+            eprintln!("{}: {}: invalid {}", program, arg, what);
+            process::exit(2);
+        }
+    }
+}
+
+// Which files found while recursing are searched, by --include and --exclude
+// globs matched against their names
+struct FileFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl FileFilter {
+    // Whether the file `name` matches an --include glob, if any were given,
+    // and no --exclude glob
+    fn accepts(&self, name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|glob| glob.is_match(name)))
+            && !self.exclude.iter().any(|glob| glob.is_match(name))
+    }
+}
+
+// Compiles a file name glob, where `*` stands for any run of chars, `?` for
+// any one char and `[...]` for one of a set, with `[!...]` negated. A set
+// that isn't a valid bracket expression, like `[z-a]`, is an error.
+fn glob_regex(program: &str, glob: &str) -> Regex {
+    let mut pattern = String::from("\\A");
+    let mut chars = glob.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            '[' if chars.as_str().contains(']') => {
+                pattern.push('[');
+                if chars.as_str().starts_with('!') {
+                    chars.next();
+                    pattern.push('^');
+                }
+                for set_char in chars.by_ref() {
+                    pattern.push(set_char);
+                    if set_char == ']' {
+                        break;
+                    }
+                }
+            }
+            _ => pattern.push_str(&Regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push_str("\\z");
+    let options = RegexOptions {
+        dotall: true,
+        ..RegexOptions::default()
+    };
+    match Regex::with_options(&pattern, options) {
+        Ok(regex) => regex,
+        Err(err) => {
+            eprintln!("{}: invalid glob '{}': {}", program, glob, err);
+            process::exit(2);
+        }
+    }
+}
+
+// Adds every regular file under `dir` that `filter` accepts to `files`, in
+// sorted order, and returns false if any directory couldn't be read. Symbolic
+// links are not followed.
+fn collect_files(
+    dir: &Path,
+    filter: &FileFilter,
+    files: &mut Vec<String>,
+    no_messages: bool,
+) -> bool {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            if !no_messages {
+                eprintln!("{}: {}", dir.display(), err);
+            }
+            return false;
+        }
+    };
+    let mut entries: Vec<_> = entries.filter_map(Result::ok).collect();
+    entries.sort_by_key(|entry| entry.file_name());
+    let mut ok = true;
+    for entry in entries {
+        let path = entry.path();
+        let path = path.strip_prefix("./").unwrap_or(&path);
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => {
+                ok &= collect_files(path, filter, files, no_messages);
+            }
+            Ok(file_type)
+                if file_type.is_file() && filter.accepts(&entry.file_name().to_string_lossy()) =>
+            {
+                files.push(path.display().to_string());
+            }
+            _ => {}
+        }
+    }
+    ok
+}
+
+// How matching lines are printed
+struct Output {
+    color: bool,
+    only_matching: bool,
+    // Prints the byte offset of each line, or with `only_matching` of each
+    // match, within its input before it
+    byte_offset: bool,
+    // Which of the matches starting at the same place -o prints and --color
+    // highlights. With --posix it is the longest, otherwise the first found.
+    semantics: MatchSemantics,
+    with_filename: bool,
+    // Lines printed before and after each matching line
+    before_context: usize,
+    after_context: usize,
+    // Matching lines after which to stop reading each input
+    max_count: Option<usize>,
+    // Set to print the names of inputs rather than their lines
+    list_files: Option<ListFiles>,
+    // Prints nothing and exits successfully at the first match
+    quiet: bool,
+    // Selects the lines that don't match rather than those that do
+    invert: bool,
+    // Prints how many lines of each input were selected instead of the lines
+    count_only: bool,
+}
+
+// Which inputs -l and -L print the names of
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ListFiles {
+    Matching,
+    NonMatching,
+}
+
+// How far the search has got: the lines around matches still to be printed as
+// context, and how many lines of the current input have been selected
+#[derive(Default)]
+struct Scan {
+    // The byte offsets and text of the latest non-matching lines not yet
+    // printed, at most `before_context`
+    before: VecDeque<(usize, String)>,
+    // Lines left to print after the last matching line
+    after_remaining: usize,
+    // Number of the last line printed from the current input
+    last_printed: Option<usize>,
+    // Whether any line has been printed from any input
+    printed_any: bool,
+    // Lines of the current input selected, which with -v are those that
+    // didn't match
+    selected_lines: usize,
+}
+
+impl Scan {
+    // Forgets the lines of the previous input
+    fn next_input(&mut self) {
+        self.before.clear();
+        self.after_remaining = 0;
+        self.last_printed = None;
+        self.selected_lines = 0;
+    }
+
+    // Whether `max_count` lines have been selected, so no more can be
+    fn at_max_count(&self, output: &Output) -> bool {
+        output.max_count.is_some_and(|max_count| self.selected_lines >= max_count)
+    }
+
+    // Whether the rest of the input can be skipped, with no more matches
+    // wanted and the context after the last one printed
+    fn finished(&self, output: &Output) -> bool {
+        self.at_max_count(output) && self.after_remaining == 0
     }
 
-    let pattern = &args[2];
-    let mut input_line = String::new();
-    io::stdin().read_line(&mut input_line).unwrap();
+    // Prints the `--` separating groups of lines before line `number`, unless
+    // it directly follows the last line printed
+    fn separate(&self, number: usize, output: &Output) {
+        let with_context = output.before_context > 0 || output.after_context > 0;
+        let adjacent = self.last_printed.is_some_and(|last| last + 1 == number);
+        if with_context && self.printed_any && !adjacent {
+            println!("--");
+        }
+    }
+}
 
-    let result = basic_match_pattern(input_line.trim(), pattern);
+// Prints each line of `text` that matches any of the patterns, or with
+// `invert` that matches none of them, or with `only_matching` each matched
+// part of the line. `name` identifies the input in messages and, with
+// `with_filename`, before each line. Returns whether any line was selected.
+//
+// Lines end at `\n`, which isn't part of the text matched. The `\r` of a
+// Windows line ending is kept, so the line prints unchanged, and `$` matches
+// before it, so `foo$` still matches `foo`.
+fn print_matches(
+    regexes: &[Regex],
+    text: &str,
+    name: &str,
+    output: &Output,
+    scan: &mut Scan,
+) -> bool {
+    let mut matched = false;
+    let mut offset = 0;
+    for (number, line) in text.split_inclusive('\n').enumerate() {
+        if scan.finished(output) {
+            break;
+        }
+        let line_offset = offset;
+        offset += line.len();
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        matched |= print_if_match(regexes, line, number + 1, line_offset, name, output, scan);
+    }
+    matched
+}
 
-    if result {
-        println!("Code 0");
+// Like `print_matches`, but reads one line at a time into a reused buffer so
+// memory use is bounded by the longest line rather than the whole input
+fn print_stream_matches(
+    regexes: &[Regex],
+    mut reader: impl BufRead,
+    name: &str,
+    output: &Output,
+    scan: &mut Scan,
+) -> io::Result<bool> {
+    let mut matched = false;
+    let mut buffer = String::new();
+    let mut number = 0;
+    let mut offset = 0;
+    while !scan.finished(output) && reader.read_line(&mut buffer)? > 0 {
+        number += 1;
+        let line = buffer.strip_suffix('\n').unwrap_or(&buffer);
+        matched |= print_if_match(regexes, line, number, offset, name, output, scan);
+        offset += buffer.len();
+        buffer.clear();
+    }
+    Ok(matched)
+}
+
+// Prints `line`, the `number`th line of its input starting `offset` bytes into
+// it, if it is selected by matching any of the patterns, or with `invert` none
+// of them, and returns whether it was. A line that isn't selected, or that
+// comes after `max_count` selected lines, is printed or kept as context instead
+// if the context options call for it. Lines that can't be searched are never
+// selected.
+fn print_if_match(
+    regexes: &[Regex],
+    line: &str,
+    number: usize,
+    offset: usize,
+    name: &str,
+    output: &Output,
+    scan: &mut Scan,
+) -> bool {
+    if scan.at_max_count(output) {
+        return print_context(line, number, offset, name, output, scan);
+    }
+    let is_match = regexes.iter().try_fold(false, |found, regex| {
+        Ok::<_, MatchError>(found || regex.try_is_match(line)?)
+    });
+    let is_match = match is_match {
+        Ok(is_match) => is_match,
+        Err(err) => {
+            eprintln!("{}:{}: {}; line skipped", name, number, err);
+            return print_context(line, number, offset, name, output, scan);
+        }
+    };
+    if is_match == output.invert {
+        return print_context(line, number, offset, name, output, scan);
+    }
+    if output.quiet {
         process::exit(0);
+    }
+
+    scan.separate(number - scan.before.len(), output);
+    for (previous_offset, previous) in scan.before.drain(..) {
+        print_line(name, Some(previous_offset), &previous, '-', output);
+    }
+    scan.last_printed = Some(number);
+    scan.after_remaining = output.after_context;
+    scan.printed_any = true;
+    scan.selected_lines += 1;
+    if output.list_files.is_some() || output.count_only {
+        return true;
+    }
+
+    if output.only_matching {
+        for (start, end) in match_spans(regexes, line, output.semantics) {
+            let part = if output.color {
+                format!("{}{}{}", MATCH_COLOR, &line[start..end], RESET_COLOR)
+            } else {
+                line[start..end].to_string()
+            };
+            print_line(name, Some(offset + start), &part, ':', output);
+        }
+        return true;
+    }
+    let line = if output.color {
+        highlight(regexes, line, output.semantics)
     } else {
-        println!("Code 1");
-        process::exit(1);
+        line.to_string()
+    };
+    print_line(name, Some(offset), &line, ':', output);
+    true
+}
+
+// Prints what -l, -L or -c report about the input `name` once it has been
+// scanned: its name if it did or didn't have selected lines, or their count
+fn print_summary(name: &str, scan: &Scan, output: &Output) {
+    if let Some(list_files) = output.list_files {
+        let selected = scan.selected_lines > 0;
+        if selected == (list_files == ListFiles::Matching) {
+            println!("{}", name);
+        }
+    } else if output.count_only {
+        print_line(name, None, &scan.selected_lines.to_string(), ':', output);
+    }
+}
+
+// Prints `line`, which wasn't selected and starts `offset` bytes into its
+// input, if it follows a selected line closely enough to be context, or else
+// keeps it in case a selected line closely follows it. Returns false, for the
+// caller to pass on as whether the line was selected.
+fn print_context(
+    line: &str,
+    number: usize,
+    offset: usize,
+    name: &str,
+    output: &Output,
+    scan: &mut Scan,
+) -> bool {
+    if scan.after_remaining > 0 {
+        scan.after_remaining -= 1;
+        scan.last_printed = Some(number);
+        print_line(name, Some(offset), line, '-', output);
+    } else if output.before_context > 0 {
+        if scan.before.len() == output.before_context {
+            scan.before.pop_front();
+        }
+        scan.before.push_back((offset, line.to_string()));
+    }
+    false
+}
+
+// Prints `line`, after `name` and `separator` when file names are shown and
+// after its byte `offset`, if it has one, and `separator` with -b. The
+// separator is `:` for selected lines and `-` for context lines.
+fn print_line(name: &str, offset: Option<usize>, line: &str, separator: char, output: &Output) {
+    let mut prefix = String::new();
+    if output.with_filename {
+        prefix.push_str(name);
+        prefix.push(separator);
+    }
+    if let Some(offset) = offset.filter(|_| output.byte_offset) {
+        prefix.push_str(&offset.to_string());
+        prefix.push(separator);
+    }
+    println!("{}{}", prefix, line);
+}
+
+// Returns the spans of the non-empty matches of any of the patterns in
+// `line`, chosen according to `semantics`, in order. Where matches overlap,
+// the one starting first wins.
+fn match_spans(regexes: &[Regex], line: &str, semantics: MatchSemantics) -> Vec<(usize, usize)> {
+    let mut spans: Vec<(usize, usize)> = regexes
+        .iter()
+        .flat_map(|regex| regex.find_iter_with(line, semantics))
+        .filter(|found| !found.as_str().is_empty())
+        .map(|found| (found.start(), found.end()))
+        .collect();
+    spans.sort();
+
+    let mut last_end = 0;
+    spans.retain(|&(start, end)| {
+        if start < last_end {
+            return false;
+        }
+        last_end = end;
+        true
+    });
+    spans
+}
+
+// Wraps every match of any of the patterns in `line` in color escapes
+fn highlight(regexes: &[Regex], line: &str, semantics: MatchSemantics) -> String {
+    let mut highlighted = String::with_capacity(line.len());
+    let mut last_end = 0;
+    for (start, end) in match_spans(regexes, line, semantics) {
+        highlighted.push_str(&line[last_end..start]);
+        highlighted.push_str(MATCH_COLOR);
+        highlighted.push_str(&line[start..end]);
+        highlighted.push_str(RESET_COLOR);
+        last_end = end;
+    }
+    highlighted.push_str(&line[last_end..]);
+    highlighted
+}
+
+// Splits a sed substitution command `s/pattern/replacement/flags` into its
+// pattern, its replacement rewritten in the `$N` syntax of
+// `Regex::replace`, and whether the `g` flag asks to replace every match
+// rather than the first. The delimiter is whatever char follows the `s`, as
+// in `s|/usr|/opt|`, and a backslash before it makes it a literal char.
+fn parse_sed(command: &str) -> Result<(String, String, bool), String> {
+    let invalid = || format!("invalid sed command '{}'", command);
+    let mut chars = command.chars();
+    if chars.next() != Some('s') {
+        return Err(invalid());
+    }
+    let delimiter = match chars.next() {
+        Some('\\' | '\n') | None => return Err(invalid()),
+        Some(delimiter) => delimiter,
+    };
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            // The delimiter is quoted in the pattern, where it may be a
+            // metacharacter, and left escaped in the replacement
+            '\\' if parts.is_empty() && chars.as_str().starts_with(delimiter) => {
+                chars.next();
+                part.push_str("\\Q");
+                part.push(delimiter);
+                part.push_str("\\E");
+            }
+            '\\' => {
+                part.push(c);
+                part.extend(chars.next());
+            }
+            _ if c == delimiter && parts.len() < 2 => parts.push(std::mem::take(&mut part)),
+            _ => part.push(c),
+        }
+    }
+    let [pattern, replacement] = <[String; 2]>::try_from(parts).map_err(|_| invalid())?;
+    let global = match part.as_str() {
+        "" => false,
+        "g" => true,
+        _ => return Err(format!("unknown flags '{}' in sed command '{}'", part, command)),
+    };
+    Ok((pattern, sed_replacement(&replacement), global))
+}
+
+// Rewrites a sed replacement, where `&` is the whole match and `\N` group N,
+// as a `Regex::replace` template
+fn sed_replacement(replacement: &str) -> String {
+    let mut template = String::with_capacity(replacement.len());
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => template.push_str("${0}"),
+            '$' => template.push_str("$$"),
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => {
+                    template.push_str("${");
+                    template.push(digit);
+                    template.push('}');
+                }
+                Some('n') => template.push('\n'),
+                Some('$') => template.push_str("$$"),
+                Some(escaped) => template.push(escaped),
+                None => template.push('\\'),
+            },
+            _ => template.push(c),
+        }
+    }
+    template
+}
+
+// Prints every line of the files, or of standard input if there are none,
+// with the first match of `regex`, or with `global` every match, replaced.
+// Returns the exit status: 0, or 2 if an input couldn't be read.
+fn run_sed(
+    regex: &Regex,
+    replacement: &str,
+    global: bool,
+    files: &[String],
+    no_messages: bool,
+) -> i32 {
+    let edit = |reader: &mut dyn BufRead| -> io::Result<()> {
+        let mut buffer = String::new();
+        while reader.read_line(&mut buffer)? > 0 {
+            let line = buffer.strip_suffix('\n').unwrap_or(&buffer);
+            if global {
+                println!("{}", regex.replace_all(line, replacement));
+            } else {
+                println!("{}", regex.replace(line, replacement));
+            }
+            buffer.clear();
+        }
+        Ok(())
+    };
+
+    if files.is_empty() {
+        return match edit(&mut io::stdin().lock()) {
+            Ok(()) => 0,
+            Err(err) => {
+                eprintln!("(standard input): {}", err);
+                2
+            }
+        };
+    }
+    let mut status = 0;
+    for path in files {
+        let result = File::open(path).and_then(|file| edit(&mut BufReader::new(file)));
+        if let Err(err) = result {
+            if !no_messages {
+                eprintln!("{}: {}", path, err);
+            }
+            status = 2;
+        }
+    }
+    status
+}
+
+#[cfg(feature = "encoding")]
+type Encoding = &'static encoding_rs::Encoding;
+
+// Without the `encoding` feature the only encoding that can be named is UTF-8
+#[cfg(not(feature = "encoding"))]
+#[derive(Clone, Copy)]
+struct Encoding;
+
+// Looks up the encoding named by `--encoding`, once, before any file is read
+#[cfg(feature = "encoding")]
+fn encoding_for(label: &str) -> Result<Encoding, String> {
+    encoding_rs::Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| format!("unknown encoding '{}'", label))
+}
+
+#[cfg(not(feature = "encoding"))]
+fn encoding_for(label: &str) -> Result<Encoding, String> {
+    if label.eq_ignore_ascii_case("utf-8") || label.eq_ignore_ascii_case("utf8") {
+        return Ok(Encoding);
+    }
+    Err(format!(
+        "encoding '{}' requires building with the `encoding` feature",
+        label
+    ))
+}
+
+// Decodes file contents as UTF-8, or with the encoding named by `--encoding`
+fn decode(bytes: Vec<u8>, encoding: Option<Encoding>) -> Result<String, String> {
+    match encoding {
+        #[cfg(feature = "encoding")]
+        Some(encoding) => Ok(encoding.decode(&bytes).0.into_owned()),
+        _ => String::from_utf8(bytes).map_err(|_| "invalid UTF-8 data".to_string()),
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,98 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::regex_matcher::{ParseError, Regex};
+
+/// Compiled patterns kept by pattern string, so a program matching with the
+/// same few patterns over and over parses each only once.
+///
+/// At most `capacity` patterns are kept. Compiling another one evicts the
+/// pattern used least recently.
+#[derive(Debug)]
+pub struct RegexCache {
+    capacity: usize,
+    // Most recently used last
+    entries: VecDeque<(String, Arc<Regex>)>,
+    compiled: usize,
+}
+
+impl RegexCache {
+    /// Creates an empty cache holding up to `capacity` patterns.
+    pub fn new(capacity: usize) -> RegexCache {
+        RegexCache {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+            compiled: 0,
+        }
+    }
+
+    /// Returns the compiled `pattern`, compiling it only if it isn't cached.
+    /// Patterns that fail to compile aren't cached.
+    pub fn get(&mut self, pattern: &str) -> Result<Arc<Regex>, ParseError> {
+        if let Some(index) = self.entries.iter().position(|(cached, _)| cached == pattern) {
+            let entry = self.entries.remove(index).unwrap();
+            let regex = Arc::clone(&entry.1);
+            self.entries.push_back(entry);
+            return Ok(regex);
+        }
+
+        let regex = Arc::new(Regex::new(pattern)?);
+        self.compiled += 1;
+        if self.capacity > 0 {
+            if self.entries.len() == self.capacity {
+                self.entries.pop_front();
+            }
+            self.entries.push_back((pattern.to_string(), Arc::clone(&regex)));
+        }
+        Ok(regex)
+    }
+
+    /// The number of patterns cached.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no patterns are cached.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The number of times a pattern has been compiled because it wasn't
+    /// cached.
+    pub fn compiled(&self) -> usize {
+        self.compiled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compiling_a_cached_pattern_again_is_a_hit() {
+        let mut cache = RegexCache::new(2);
+        let first = cache.get(r"\d+").unwrap();
+        let second = cache.get(r"\d+").unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(cache.compiled(), 1);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn least_recently_used_pattern_is_evicted() {
+        let mut cache = RegexCache::new(2);
+        for pattern in ["a", "b", "a", "c", "a", "b"] {
+            cache.get(pattern).unwrap();
+        }
+        // `b` was evicted by `c`, so it's compiled a second time
+        assert_eq!(cache.compiled(), 4);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn invalid_patterns_are_not_cached() {
+        let mut cache = RegexCache::new(2);
+        assert!(cache.get("(a").is_err());
+        assert!(cache.is_empty());
+    }
+}
@@ -1,74 +1,456 @@
+#[cfg(feature = "trace")]
+use std::cell::RefCell;
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::fmt;
+use std::io::{self, Read};
 use std::iter::Peekable;
 use std::str::Chars;
+use std::sync::Arc;
 
-#[derive(Debug, Clone)]
+/// One element of a parsed pattern, as returned by [`parse`].
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Pattern {
+    // Matches the empty string, as an empty alternative does
+    Empty,
     Literal(String),
     Digit,
     Alphanumeric,
+    // Any char except `\n`, so a match never runs on past the end of a line,
+    // unless the `dotall` option is set
     AnyChar,
     Start,
     End,
-    CharGroup(Vec<char>, bool),
-    OneOrMore(Box<Pattern>),    
-    ZeroOrOne(Box<Pattern>),   
+    // The start and end of the whole input, even with the `multiline` option
+    InputStart,
+    InputEnd,
+    WordBoundary,
+    NonWordBoundary,
+    CharGroup(Vec<ClassItem>, bool),
+    // Possessive quantifiers never give back a repetition once taken
+    OneOrMore { pattern: Box<Pattern>, lazy: bool, possessive: bool },
+    ZeroOrMore { pattern: Box<Pattern>, lazy: bool, possessive: bool },
+    ZeroOrOne { pattern: Box<Pattern>, lazy: bool, possessive: bool },
+    Repeat { pattern: Box<Pattern>, min: usize, max: Option<usize>, lazy: bool, possessive: bool },
     Alternation(Vec<Pattern>), 
-    Group(Vec<Pattern>),        
-    BackReference(usize),       
+    // Capturing groups carry their 1-based index; alternatives are uncaptured sequences
+    Group(Vec<Pattern>, Option<usize>),
+    // Matches at the current position without consuming input, succeeding if
+    // `inner` matches there, or with `negate` if it doesn't
+    Lookahead { inner: Vec<Pattern>, negate: bool },
+    // Like `Lookahead`, but `inner` must match the `width` chars that end at
+    // the current position
+    Lookbehind { inner: Vec<Pattern>, negate: bool, width: usize },
+    // Matches the text the group with this index last captured on the current
+    // match path, so a group inside a repetition or a retried alternation
+    // refers to its latest capture. Fails if the group hasn't captured yet.
+    // With the bool set, as under `(?i)`, the text may match in any case.
+    BackReference(usize, bool),
+}
+
+/// One member of a bracket expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClassItem {
+    Char(char),
+    /// The chars from the first to the second inclusive, as in `a-z`.
+    Range(char, char),
+    Posix(PosixClass),
+}
+
+/// A named POSIX character class such as `[:alpha:]`, usable inside a bracket
+/// expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PosixClass {
+    Alnum,
+    Alpha,
+    Blank,
+    Cntrl,
+    Digit,
+    Graph,
+    Lower,
+    Print,
+    Punct,
+    Space,
+    Upper,
+    Xdigit,
+}
+
+impl PosixClass {
+    /// Looks up a class by the name written between `[:` and `:]`.
+    pub fn from_name(name: &str) -> Option<PosixClass> {
+        Some(match name {
+            "alnum" => PosixClass::Alnum,
+            "alpha" => PosixClass::Alpha,
+            "blank" => PosixClass::Blank,
+            "cntrl" => PosixClass::Cntrl,
+            "digit" => PosixClass::Digit,
+            "graph" => PosixClass::Graph,
+            "lower" => PosixClass::Lower,
+            "print" => PosixClass::Print,
+            "punct" => PosixClass::Punct,
+            "space" => PosixClass::Space,
+            "upper" => PosixClass::Upper,
+            "xdigit" => PosixClass::Xdigit,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PosixClass::Alnum => "alnum",
+            PosixClass::Alpha => "alpha",
+            PosixClass::Blank => "blank",
+            PosixClass::Cntrl => "cntrl",
+            PosixClass::Digit => "digit",
+            PosixClass::Graph => "graph",
+            PosixClass::Lower => "lower",
+            PosixClass::Print => "print",
+            PosixClass::Punct => "punct",
+            PosixClass::Space => "space",
+            PosixClass::Upper => "upper",
+            PosixClass::Xdigit => "xdigit",
+        }
+    }
+
+    /// Returns whether `c` belongs to the class. Letters, digits and spaces
+    /// follow Unicode; `punct` and `xdigit` are ASCII-only.
+    pub fn matches(self, c: char) -> bool {
+        match self {
+            PosixClass::Alnum => c.is_alphanumeric(),
+            PosixClass::Alpha => c.is_alphabetic(),
+            PosixClass::Blank => c == ' ' || c == '\t',
+            PosixClass::Cntrl => c.is_control(),
+            PosixClass::Digit => c.is_ascii_digit(),
+            PosixClass::Graph => !c.is_whitespace() && !c.is_control(),
+            PosixClass::Lower => c.is_lowercase(),
+            PosixClass::Print => !c.is_control(),
+            PosixClass::Punct => c.is_ascii_punctuation(),
+            PosixClass::Space => c.is_whitespace(),
+            PosixClass::Upper => c.is_uppercase(),
+            PosixClass::Xdigit => c.is_ascii_hexdigit(),
+        }
+    }
+}
+
+/// How to choose between several ways a pattern can match at the same start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchSemantics {
+    /// Perl-style: the first alternative and preferred repetition count that
+    /// lead to a match win.
+    #[default]
+    LeftmostFirst,
+    /// POSIX-style: the choice that consumes the most input wins.
+    LeftmostLongest,
+    /// The choice that consumes the least input wins.
+    LeftmostShortest,
 }
 
 // Display implementation for Pattern
 impl fmt::Display for Pattern {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Pattern::Literal(s) => write!(f, "{}", s),
+            Pattern::Empty => Ok(()),
+            Pattern::Literal(s) => {
+                for c in s.chars() {
+                    match c {
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        '\r' => write!(f, "\\r")?,
+                        '\u{c}' => write!(f, "\\f")?,
+                        _ if c.is_ascii_control() => write!(f, "\\x{:02x}", c as u32)?,
+                        _ if is_metacharacter(c) => write!(f, "\\{}", c)?,
+                        _ => write!(f, "{}", c)?,
+                    }
+                }
+                Ok(())
+            }
             Pattern::Digit => write!(f, "\\d"),
             Pattern::Alphanumeric => write!(f, "\\w"),
             Pattern::AnyChar => write!(f, "."),
             Pattern::Start => write!(f, "^"),
             Pattern::End => write!(f, "$"),
-            Pattern::CharGroup(chars, is_negative) => {
+            Pattern::InputStart => write!(f, "\\A"),
+            Pattern::InputEnd => write!(f, "\\z"),
+            Pattern::WordBoundary => write!(f, "\\b"),
+            Pattern::NonWordBoundary => write!(f, "\\B"),
+            // The `.` of a pattern with the `s` flag
+            Pattern::CharGroup(items, true) if items.is_empty() => write!(f, "(?s:.)"),
+            // A lone `^` can't be written as a bracket expression
+            Pattern::CharGroup(items, false) if items == &[ClassItem::Char('^')] => {
+                write!(f, "\\^")
+            }
+            Pattern::CharGroup(items, is_negative) => {
                 let mut s = String::new();
                 s.push('[');
                 if *is_negative {
                     s.push('^');
                 }
-                s.extend(chars.iter());
+                for item in display_order(items, *is_negative) {
+                    match item {
+                        ClassItem::Char(c) => s.push(*c),
+                        ClassItem::Range(first, last) => {
+                            s.push(*first);
+                            s.push('-');
+                            s.push(*last);
+                        }
+                        ClassItem::Posix(class) => {
+                            s.push_str("[:");
+                            s.push_str(class.name());
+                            s.push_str(":]");
+                        }
+                    }
+                }
                 s.push(']');
                 write!(f, "{}", s)
             }
-            Pattern::OneOrMore(p) => write!(f, "{}+", p),
-            Pattern::ZeroOrOne(p) => write!(f, "{}?", p),
+            Pattern::OneOrMore { pattern, lazy, possessive } => {
+                write!(f, "{}+{}", pattern, quantifier_suffix(*lazy, *possessive))
+            }
+            Pattern::ZeroOrMore { pattern, lazy, possessive } => {
+                write!(f, "{}*{}", pattern, quantifier_suffix(*lazy, *possessive))
+            }
+            Pattern::ZeroOrOne { pattern, lazy, possessive } => {
+                write!(f, "{}?{}", pattern, quantifier_suffix(*lazy, *possessive))
+            }
+            Pattern::Repeat { pattern, min, max, lazy, possessive } => {
+                let suffix = quantifier_suffix(*lazy, *possessive);
+                match max {
+                    Some(max) if max == min => write!(f, "{}{{{}}}{}", pattern, min, suffix),
+                    Some(max) => write!(f, "{}{{{},{}}}{}", pattern, min, max, suffix),
+                    None => write!(f, "{}{{{},}}{}", pattern, min, suffix),
+                }
+            }
+            // Parsed alternations are either the whole pattern or the whole
+            // body of a group, so the group supplies any parentheses, and each
+            // branch is a non-capturing group only there to hold a sequence
             Pattern::Alternation(alternatives) => {
-                let mut s = String::new();
-                s.push('(');
-                let len = alternatives.len();
                 for (i, alt) in alternatives.iter().enumerate() {
-                    s.push_str(&alt.to_string());
-                    if i < len - 1 {
-                        s.push('|');
+                    if i > 0 {
+                        write!(f, "|")?;
+                    }
+                    match alt {
+                        Pattern::Group(subpatterns, None) => {
+                            for subpattern in subpatterns {
+                                write!(f, "{}", subpattern)?;
+                            }
+                        }
+                        _ => write!(f, "{}", alt)?,
                     }
                 }
-                s.push(')');
-                write!(f, "{}", s)
+                Ok(())
             }
-            Pattern::Group(subpatterns) => {
-                let mut s = String::new();
-                s.push('(');
+            Pattern::Group(subpatterns, index) => {
+                write!(f, "{}", if index.is_some() { "(" } else { "(?:" })?;
                 for subpattern in subpatterns {
-                    s.push_str(&subpattern.to_string());
+                    write!(f, "{}", subpattern)?;
                 }
-                s.push(')');
-                write!(f, "{}", s)
+                write!(f, ")")
+            }
+            Pattern::Lookahead { inner, negate } | Pattern::Lookbehind { inner, negate, .. } => {
+                let behind = if matches!(self, Pattern::Lookbehind { .. }) { "<" } else { "" };
+                write!(f, "(?{}{}", behind, if *negate { '!' } else { '=' })?;
+                for pattern in inner {
+                    write!(f, "{}", pattern)?;
+                }
+                write!(f, ")")
+            }
+            Pattern::BackReference(n, false) => write!(f, "\\{}", n),
+            Pattern::BackReference(n, true) => write!(f, "(?i:\\{})", n),
+        }
+    }
+}
+
+// Characters that need a backslash to be matched literally
+fn is_metacharacter(c: char) -> bool {
+    matches!(
+        c,
+        '\\' | '.' | '^' | '$' | '[' | '(' | ')' | '|' | '*' | '+' | '?' | '{'
+    )
+}
+
+// The members of a bracket expression in an order that parses back to the
+// same members: a `]` is only a member first, a `^` anywhere but first, and a
+// `-` at either end, kept first if it came first
+fn display_order(items: &[ClassItem], is_negative: bool) -> Vec<&ClassItem> {
+    let is_char = |item: &ClassItem, c: char| *item == ClassItem::Char(c);
+    let starts_with_bracket =
+        |item: &ClassItem| matches!(item, ClassItem::Char(']') | ClassItem::Range(']', _));
+    let mut ordered: Vec<&ClassItem> =
+        items.iter().filter(|item| starts_with_bracket(item)).collect();
+    let carets = items.iter().filter(|item| is_char(item, '^'));
+    let hyphens = items.iter().filter(|item| is_char(item, '-'));
+    let others = items.iter().filter(|item| {
+        !starts_with_bracket(item) && !is_char(item, '^') && !is_char(item, '-')
+    });
+    if ordered.is_empty() && items.first().is_some_and(|item| is_char(item, '-')) {
+        ordered.extend(hyphens.chain(others).chain(carets));
+    } else {
+        ordered.extend(others);
+        if ordered.is_empty() && !is_negative {
+            ordered.extend(hyphens.chain(carets));
+        } else {
+            ordered.extend(carets.chain(hyphens));
+        }
+    }
+    ordered
+}
+
+fn quantifier_suffix(lazy: bool, possessive: bool) -> &'static str {
+    if lazy {
+        "?"
+    } else if possessive {
+        "+"
+    } else {
+        ""
+    }
+}
+
+/// An error describing why a pattern could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ParseError {
+    #[error("unterminated character class")]
+    UnterminatedClass,
+    #[error("unmatched ( in pattern")]
+    UnmatchedParen,
+    #[error("invalid character class name '{0}'")]
+    InvalidClassName(String),
+    #[error("invalid range end in character class: {0}-{1}")]
+    InvalidRange(char, char),
+    #[error("unsupported group syntax after (?")]
+    InvalidGroup,
+    #[error("invalid group name '{0}'")]
+    InvalidGroupName(String),
+    #[error("duplicate group name '{0}'")]
+    DuplicateGroupName(String),
+    #[error("lookbehind must match a fixed number of characters")]
+    VariableLookbehind,
+    #[error("\\x must be followed by two hex digits")]
+    InvalidHexEscape,
+    #[error("\\u must be followed by a Unicode scalar value in hex, as in \\u{{1F600}}")]
+    InvalidUnicodeEscape,
+    #[error("quantifier follows another quantifier")]
+    InvalidQuantifier,
+    #[error("trailing backslash")]
+    TrailingBackslash,
+    #[error("invalid repetition bounds: {{{0},{1}}}")]
+    InvalidRepeatBounds(usize, usize),
+}
+
+/// An error that stopped a search before it could decide whether the input
+/// matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum MatchError {
+    #[error("search exceeded the step limit")]
+    ComplexityLimitExceeded,
+    #[error("pattern contains non-ASCII characters, which can't match bytes")]
+    NonAsciiPattern,
+}
+
+/// Parses `pattern` into the sequence of elements a match must match in
+/// order, such as `[Literal("ab"), OneOrMore { .. }]` for `abc+`.
+pub fn parse(pattern: &str) -> Result<Vec<Pattern>, ParseError> {
+    parse_alternatives(pattern, &mut Vec::new(), Flags::default())
+}
+
+/// Parses `pattern`; see [`parse`].
+pub fn parse_pattern(pattern: &str) -> Result<Vec<Pattern>, ParseError> {
+    parse(pattern)
+}
+
+/// Checks that `pattern` parses, returning the error that compiling it would,
+/// without building anything to match with.
+pub fn validate(pattern: &str) -> Result<(), ParseError> {
+    parse(pattern).map(|_| ())
+}
+
+/// Rewrites a POSIX basic regular expression in the extended syntax this
+/// module parses.
+///
+/// In basic syntax `(`, `)`, `{`, `}`, `|`, `+` and `?` are literals and only
+/// act as operators when escaped, the reverse of extended syntax. Bracket
+/// expressions and `\Q...\E` spans are copied unchanged.
+pub fn basic_to_extended(pattern: &str) -> String {
+    let mut extended = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(next @ ('(' | ')' | '{' | '}' | '|' | '+' | '?')) => extended.push(next),
+                // The braces of a `\u{...}` escape are part of the escape
+                Some('u') if chars.as_str().starts_with('{') => {
+                    extended.push_str("\\u");
+                    for escape_char in chars.by_ref() {
+                        extended.push(escape_char);
+                        if escape_char == '}' {
+                            break;
+                        }
+                    }
+                }
+                Some('Q') => {
+                    extended.push_str("\\Q");
+                    copy_quoted_span(&mut chars, &mut extended);
+                }
+                Some(next) => {
+                    extended.push('\\');
+                    extended.push(next);
+                }
+                None => extended.push('\\'),
+            },
+            '(' | ')' | '{' | '}' | '|' | '+' | '?' => {
+                extended.push('\\');
+                extended.push(c);
+            }
+            '[' => {
+                extended.push(c);
+                copy_bracket_expression(&mut chars, &mut extended);
             }
-            Pattern::BackReference(n) => write!(f, "\\{}", n),
+            _ => extended.push(c),
+        }
+    }
+    extended
+}
+
+// Copies the rest of a `\Q...\E` span after its `\Q` into `out`, up to and
+// including the `\E` or else to the end of the pattern
+fn copy_quoted_span(chars: &mut impl Iterator<Item = char>, out: &mut String) {
+    let mut previous = None;
+    for quoted_char in chars.by_ref() {
+        out.push(quoted_char);
+        if previous == Some('\\') && quoted_char == 'E' {
+            break;
         }
+        previous = Some(quoted_char);
     }
 }
 
-pub fn parse_pattern(pattern: &str) -> Vec<Pattern> {
+// Copies the rest of a bracket expression after its opening `[` into `out`, up
+// to and including the closing `]`. Metacharacters inside it are plain
+// members, and neither a `]` first in the expression nor one closing a
+// `[:name:]` class ends it.
+fn copy_bracket_expression(chars: &mut impl Iterator<Item = char>, out: &mut String) {
+    let mut in_class_name = false;
+    let mut previous = None;
+    for (i, class_char) in chars.by_ref().enumerate() {
+        out.push(class_char);
+        let first = i == 0 || (i == 1 && previous == Some('^'));
+        match class_char {
+            ':' if previous == Some('[') => in_class_name = true,
+            ']' if in_class_name => in_class_name = previous != Some(':'),
+            ']' if !first => break,
+            _ => {}
+        }
+        previous = Some(class_char);
+    }
+}
+
+// Parses a pattern, numbering capturing groups in order of their opening
+// parenthesis after those already in `group_names`, which gets the name, if
+// any, of each new group. `flags` are the inline flags in effect at its start.
+fn parse_sequence(
+    pattern: &str,
+    group_names: &mut Vec<Option<String>>,
+    flags: Flags,
+) -> Result<Vec<Pattern>, ParseError> {
     let mut patterns = Vec::new();
     let mut chars = pattern.chars().peekable();
     let mut literal_buffer = String::new(); 
@@ -77,19 +459,30 @@ pub fn parse_pattern(pattern: &str) -> Vec<Pattern> {
         match c {
             '\\' => {
                 // Peek the next character to determine the type
-                if let Some(&next_char) = chars.peek() {
-                    if next_char.is_digit(10) {
-                        // It's a backreference like \1, \2, etc.
-                        chars.next(); // Consume the digit
-                        let group_num = next_char.to_digit(10).unwrap() as usize;
-                        // Flush the buffer before handling backreference
-                        if !literal_buffer.is_empty() {
-                            patterns.push(Pattern::Literal(literal_buffer.clone()));
-                            literal_buffer.clear();
+                // It's a backreference like \1, \2, etc. The number takes every
+                // digit that follows, so \10 refers to group 10 rather than
+                // group 1 followed by a literal 0
+                if chars.peek().is_some_and(char::is_ascii_digit) {
+                    // A number too large to parse names a group that can't exist
+                    let group_num = parse_number(&mut chars).unwrap_or(usize::MAX);
+                    // Flush the buffer before handling backreference
+                    if !literal_buffer.is_empty() {
+                        patterns.push(Pattern::Literal(literal_buffer.clone()));
+                        literal_buffer.clear();
+                    }
+                    patterns.push(Pattern::BackReference(group_num, false));
+                    continue;
+                }
+
+                // Everything up to `\E`, or the end of the pattern, is literal
+                if chars.next_if_eq(&'Q').is_some() {
+                    while let Some(quoted) = chars.next() {
+                        if quoted == '\\' && chars.next_if_eq(&'E').is_some() {
+                            break;
                         }
-                        patterns.push(Pattern::BackReference(group_num));
-                        continue;
+                        literal_buffer.push(quoted);
                     }
+                    continue;
                 }
 
                 // Handle escaped characters
@@ -97,15 +490,24 @@ pub fn parse_pattern(pattern: &str) -> Vec<Pattern> {
                     patterns.push(Pattern::Literal(literal_buffer.clone()));
                     literal_buffer.clear();
                 }
-                if let Some(escaped) = chars.next() {
-                    let pattern = match escaped {
-                        'd' => Pattern::Digit,
-                        'w' => Pattern::Alphanumeric,
-                        '\\' => Pattern::Literal("\\".to_string()),
-                        _ => Pattern::Literal(escaped.to_string()),
-                    };
-                    patterns.push(pattern);
-                }
+                let escaped = chars.next().ok_or(ParseError::TrailingBackslash)?;
+                let pattern = match escaped {
+                    'd' => Pattern::Digit,
+                    'w' => Pattern::Alphanumeric,
+                    'b' => Pattern::WordBoundary,
+                    'B' => Pattern::NonWordBoundary,
+                    'A' => Pattern::InputStart,
+                    'z' => Pattern::InputEnd,
+                    'n' => Pattern::Literal("\n".to_string()),
+                    't' => Pattern::Literal("\t".to_string()),
+                    'r' => Pattern::Literal("\r".to_string()),
+                    'f' => Pattern::Literal("\u{c}".to_string()),
+                    'x' => Pattern::Literal(parse_hex_escape(&mut chars)?.to_string()),
+                    'u' => Pattern::Literal(parse_unicode_escape(&mut chars)?.to_string()),
+                    '\\' => Pattern::Literal("\\".to_string()),
+                    _ => Pattern::Literal(escaped.to_string()),
+                };
+                patterns.push(pattern);
             }
             '.' => {
                 // Flush the buffer before handling special patterns
@@ -142,11 +544,21 @@ pub fn parse_pattern(pattern: &str) -> Vec<Pattern> {
                     chars.next(); 
                 }
                 let mut group = Vec::new();
-                while let Some(group_char) = chars.next() {
-                    if group_char == ']' {
-                        break;
+                // A `]` straight after the `[` or `[^` is a member, not the end
+                if chars.next_if_eq(&']').is_some() {
+                    group.push(take_range(&mut chars, ']')?);
+                }
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some('[') if chars.peek() == Some(&':') => {
+                            chars.next();
+                            let class = parse_class_name(&mut chars)?;
+                            group.push(ClassItem::Posix(class));
+                        }
+                        Some(group_char) => group.push(take_range(&mut chars, group_char)?),
+                        None => return Err(ParseError::UnterminatedClass),
                     }
-                    group.push(group_char);
                 }
                 patterns.push(Pattern::CharGroup(group, is_negative));
             }
@@ -156,81 +568,100 @@ pub fn parse_pattern(pattern: &str) -> Vec<Pattern> {
                     patterns.push(Pattern::Literal(literal_buffer.clone()));
                     literal_buffer.clear();
                 }
-                // Parse the group
-                let mut group_pattern = String::new();
-                let mut depth = 1;
-                while let Some(next_char) = chars.next() {
-                    if next_char == '(' {
-                        depth += 1;
-                        group_pattern.push(next_char);
-                    } else if next_char == ')' {
-                        depth -= 1;
-                        if depth == 0 {
-                            break;
+                // `(?<name>` opens a named group, while `(?=` and `(?!` open
+                // lookaheads and `(?<=` and `(?<!` lookbehinds, none of which
+                // capture. `(?i)`, `(?m)` and `(?s)` set flags for the rest of
+                // the sequence, and `(?i:` and `(?:` open groups that don't
+                // capture.
+                let mut name = None;
+                if chars.next_if_eq(&'?').is_some() {
+                    if matches!(chars.peek(), Some('i' | 'm' | 's' | ':')) {
+                        let (group_flags, scoped) = parse_flags(&mut chars, flags)?;
+                        if scoped {
+                            let body = take_group_body(&mut chars)?;
+                            let mut inner = parse_alternatives(&body, group_names, group_flags)?;
+                            // `(?s:.)` and `(?i:\1)` are how a `.` or a
+                            // back-reference under a flag is displayed, so they
+                            // parse back to the lone pattern, not a group
+                            match inner.as_slice() {
+                                [Pattern::CharGroup(items, true)] if items.is_empty() => {
+                                    patterns.push(inner.remove(0))
+                                }
+                                [Pattern::BackReference(_, true)] => patterns.push(inner.remove(0)),
+                                _ => patterns.push(Pattern::Group(inner, None)),
+                            }
+                            continue;
                         }
-                        group_pattern.push(next_char);
+                        let rest: String = chars.collect();
+                        let mut patterns = apply_flags(patterns, flags);
+                        patterns.extend(parse_sequence(&rest, group_names, group_flags)?);
+                        return Ok(merge_literals(patterns));
+                    }
+                    let mut lookahead = chars.clone();
+                    if lookahead.next() == Some('<') && !matches!(lookahead.peek(), Some('=' | '!')) {
+                        chars = lookahead;
+                        name = Some(parse_group_name(&mut chars, group_names)?);
                     } else {
-                        group_pattern.push(next_char);
+                        let behind = chars.next_if_eq(&'<').is_some();
+                        let negate = match chars.next() {
+                            Some('=') => false,
+                            Some('!') => true,
+                            _ => return Err(ParseError::InvalidGroup),
+                        };
+                        let body = take_group_body(&mut chars)?;
+                        let inner = parse_alternatives(&body, group_names, flags)?;
+                        if !behind {
+                            patterns.push(Pattern::Lookahead { inner, negate });
+                            continue;
+                        }
+                        let width = match sequence_length_bounds(&inner, None) {
+                            (min, Some(max)) if min == max => min,
+                            _ => return Err(ParseError::VariableLookbehind),
+                        };
+                        patterns.push(Pattern::Lookbehind { inner, negate, width });
+                        continue;
                     }
                 }
-                let group_patterns = parse_group_pattern(&group_pattern);
-                patterns.push(Pattern::Group(group_patterns));
-            }
-            '|' => {
-                if !literal_buffer.is_empty() {
-                    patterns.push(Pattern::Literal(literal_buffer.clone()));
-                    literal_buffer.clear();
-                }
-                patterns.push(Pattern::Literal("|".to_string()));
+                group_names.push(name);
+                let group_index = group_names.len();
+                let group_pattern = take_group_body(&mut chars)?;
+                let group_patterns = parse_alternatives(&group_pattern, group_names, flags)?;
+                patterns.push(Pattern::Group(group_patterns, Some(group_index)));
             }
-            '+' => {
-                if !literal_buffer.is_empty() {
-                    if literal_buffer.len() > 1 {
-                        let mut chars_buffer: Vec<char> = literal_buffer.chars().collect();
-                        let last_char = chars_buffer.pop().unwrap();
-                        if !chars_buffer.is_empty() {
-                            let remaining = chars_buffer.into_iter().collect::<String>();
-                            patterns.push(Pattern::Literal(remaining));
-                        }
-                        let literal = Pattern::Literal(last_char.to_string());
-                        let one_or_more = Pattern::OneOrMore(Box::new(literal));
-                        patterns.push(one_or_more);
-                    } else {
-                        let literal = Pattern::Literal(literal_buffer.clone());
-                        let one_or_more = Pattern::OneOrMore(Box::new(literal));
-                        patterns.push(one_or_more);
-                    }
-                    literal_buffer.clear();
-                } else if let Some(last) = patterns.pop() {
-                    let one_or_more = Pattern::OneOrMore(Box::new(last));
-                    patterns.push(one_or_more);
+            '+' | '*' | '?' => {
+                if let Some(last) = take_quantified(&mut patterns, &mut literal_buffer) {
+                    let pattern = Box::new(last);
+                    let lazy = take_marker(&mut chars, '?');
+                    let possessive = !lazy && take_marker(&mut chars, '+');
+                    patterns.push(match c {
+                        '+' => Pattern::OneOrMore { pattern, lazy, possessive },
+                        '*' => Pattern::ZeroOrMore { pattern, lazy, possessive },
+                        _ => Pattern::ZeroOrOne { pattern, lazy, possessive },
+                    });
+                    reject_stacked_quantifier(&chars)?;
                 } else {
-                    patterns.push(Pattern::Literal("+".to_string()));
+                    patterns.push(Pattern::Literal(c.to_string()));
                 }
             }
-            '?' => {
-                if !literal_buffer.is_empty() {
-                    if literal_buffer.len() > 1 {
-                        let mut chars_buffer: Vec<char> = literal_buffer.chars().collect();
-                        let last_char = chars_buffer.pop().unwrap();
-                        if !chars_buffer.is_empty() {
-                            let remaining = chars_buffer.into_iter().collect::<String>();
-                            patterns.push(Pattern::Literal(remaining));
-                        }
-                        let literal = Pattern::Literal(last_char.to_string());
-                        let zero_or_one = Pattern::ZeroOrOne(Box::new(literal));
-                        patterns.push(zero_or_one);
-                    } else {
-                        let literal = Pattern::Literal(literal_buffer.clone());
-                        let zero_or_one = Pattern::ZeroOrOne(Box::new(literal));
-                        patterns.push(zero_or_one);
+            '{' => {
+                // A brace that doesn't form a valid `{n}`, `{n,}`, `{,m}` or `{n,m}` is a literal
+                let mut lookahead = chars.clone();
+                let quantified = parse_repeat_bounds(&mut lookahead).and_then(|bounds| {
+                    Some((bounds, take_quantified(&mut patterns, &mut literal_buffer)?))
+                });
+                match quantified {
+                    Some(((min, Some(max)), _)) if max < min => {
+                        return Err(ParseError::InvalidRepeatBounds(min, max));
                     }
-                    literal_buffer.clear();
-                } else if let Some(last) = patterns.pop() {
-                    let zero_or_one = Pattern::ZeroOrOne(Box::new(last));
-                    patterns.push(zero_or_one);
-                } else {
-                    patterns.push(Pattern::Literal("?".to_string()));
+                    Some(((min, max), last)) => {
+                        chars = lookahead;
+                        let pattern = Box::new(last);
+                        let lazy = take_marker(&mut chars, '?');
+                        let possessive = !lazy && take_marker(&mut chars, '+');
+                        patterns.push(Pattern::Repeat { pattern, min, max, lazy, possessive });
+                        reject_stacked_quantifier(&chars)?;
+                    }
+                    None => literal_buffer.push(c),
                 }
             }
             _ => {
@@ -244,337 +675,2820 @@ pub fn parse_pattern(pattern: &str) -> Vec<Pattern> {
         patterns.push(Pattern::Literal(literal_buffer.clone()));
     }
 
-    patterns
+    Ok(merge_literals(apply_flags(patterns, flags)))
 }
 
-fn parse_group_pattern(group_pattern: &str) -> Vec<Pattern> {
-    let mut alternatives = Vec::new();
-    let mut current = String::new();
-    let mut chars = group_pattern.chars().peekable();
-    let mut depth = 0;
+// Inline flags in effect while parsing, turned on by `(?i)`, `(?m)` and `(?s)`
+// or by the options a pattern is compiled with
+#[derive(Debug, Clone, Copy, Default)]
+struct Flags {
+    case_insensitive: bool,
+    multiline: bool,
+    dotall: bool,
+}
 
-    while let Some(c) = chars.next() {
-        match c {
-            '(' => {
-                depth += 1;
-                current.push(c);
+// Reads the letters of an inline flag group such as `(?is)` or `(?i:` after
+// its `(?`, through the `)` or `:`, returning `flags` with theirs turned on and
+// whether a `:` opens a group they are scoped to
+fn parse_flags(
+    chars: &mut Peekable<Chars>,
+    mut flags: Flags,
+) -> Result<(Flags, bool), ParseError> {
+    loop {
+        match chars.next() {
+            Some('i') => flags.case_insensitive = true,
+            Some('m') => flags.multiline = true,
+            Some('s') => flags.dotall = true,
+            Some(')') => return Ok((flags, false)),
+            Some(':') => return Ok((flags, true)),
+            _ => return Err(ParseError::InvalidGroup),
+        }
+    }
+}
+
+// Rewrites the patterns of a sequence parsed under `flags`. With `i` every
+// char with other cases also matches them, with `m` a `^` or `$` also matches
+// next to a `\n`, and with `s` a `.` also matches `\n`. Groups were rewritten
+// as they were parsed, but repeated patterns weren't.
+fn apply_flags(patterns: Vec<Pattern>, flags: Flags) -> Vec<Pattern> {
+    if !flags.case_insensitive && !flags.multiline && !flags.dotall {
+        return patterns;
+    }
+    patterns
+        .into_iter()
+        .flat_map(|pattern| match pattern {
+            Pattern::Literal(literal) if flags.case_insensitive => literal
+                .chars()
+                .map(|c| match case_variants(c).as_slice() {
+                    [_] => Pattern::Literal(c.to_string()),
+                    variants => {
+                        let items = variants.iter().map(|&c| ClassItem::Char(c)).collect();
+                        Pattern::CharGroup(items, false)
+                    }
+                })
+                .collect(),
+            Pattern::CharGroup(items, is_negative) if flags.case_insensitive => {
+                vec![Pattern::CharGroup(fold_class_items(items), is_negative)]
             }
-            ')' => {
-                depth -= 1;
-                current.push(c);
+            Pattern::BackReference(group_num, _) if flags.case_insensitive => {
+                vec![Pattern::BackReference(group_num, true)]
             }
-            '|' if depth == 0 => {
-                let alternative_patterns = parse_pattern(&current);
-                alternatives.push(Pattern::Group(alternative_patterns));
-                current.clear();
+            // `(?<![^\n])`, at the start of the input or after a `\n`
+            Pattern::Start if flags.multiline => vec![Pattern::Lookbehind {
+                inner: vec![Pattern::CharGroup(vec![ClassItem::Char('\n')], true)],
+                negate: true,
+                width: 1,
+            }],
+            // `(?=\r?(\n|\z))`, skipping a `\r` as `$` does
+            Pattern::End if flags.multiline => vec![Pattern::Lookahead {
+                inner: vec![
+                    Pattern::ZeroOrOne {
+                        pattern: Box::new(Pattern::Literal("\r".to_string())),
+                        lazy: false,
+                        possessive: false,
+                    },
+                    Pattern::Alternation(vec![Pattern::Literal("\n".to_string()), Pattern::InputEnd]),
+                ],
+                negate: false,
+            }],
+            // An empty negated class matches any char at all
+            Pattern::AnyChar if flags.dotall => vec![Pattern::CharGroup(Vec::new(), true)],
+            mut pattern => {
+                if let Pattern::OneOrMore { pattern: repeated, .. }
+                | Pattern::ZeroOrMore { pattern: repeated, .. }
+                | Pattern::ZeroOrOne { pattern: repeated, .. }
+                | Pattern::Repeat { pattern: repeated, .. } = &mut pattern
+                {
+                    let inner = std::mem::replace(&mut **repeated, Pattern::Empty);
+                    let mut rewritten = apply_flags(vec![inner], flags);
+                    **repeated = match rewritten.len() {
+                        1 => rewritten.remove(0),
+                        _ => Pattern::Group(rewritten, None),
+                    };
+                }
+                vec![pattern]
             }
-            _ => {
-                current.push(c);
+        })
+        .collect()
+}
+
+// `c` and its lowercase and uppercase forms, where those are single chars
+fn case_variants(c: char) -> Vec<char> {
+    let mut variants = vec![c];
+    let lower: Vec<char> = c.to_lowercase().collect();
+    let upper: Vec<char> = c.to_uppercase().collect();
+    for other in [lower, upper] {
+        if let [other] = other[..] {
+            if !variants.contains(&other) {
+                variants.push(other);
             }
         }
     }
-
-    if !current.is_empty() {
-        let alternative_patterns = parse_pattern(&current);
-        alternatives.push(Pattern::Group(alternative_patterns));
-    }
-
-    vec![Pattern::Alternation(alternatives)]
+    variants
 }
 
-fn match_class(pattern: &Pattern, input_chars: &mut Peekable<Chars>) -> bool {
-    match pattern {
-        Pattern::Digit => input_chars.next().map_or(false, |c| c.is_digit(10)),
-        Pattern::Alphanumeric => input_chars.next().map_or(false, |c| c.is_alphanumeric()),
-        Pattern::AnyChar => input_chars.next().is_some(),
-        Pattern::CharGroup(group, is_negative) => {
-            input_chars
-                .next()
-                .map_or(false, |c| group.contains(&c) != *is_negative)
-        }
-        Pattern::Start | Pattern::End => true, 
-        _ => false,
+// Adds the other cases of the members of a bracket expression. Ranges are
+// only folded when they lie within the ASCII lowercase or uppercase letters.
+fn fold_class_items(items: Vec<ClassItem>) -> Vec<ClassItem> {
+    let mut folded = Vec::with_capacity(items.len() * 2);
+    for item in items {
+        match item {
+            ClassItem::Char(c) => {
+                folded.extend(case_variants(c).into_iter().map(ClassItem::Char));
+            }
+            ClassItem::Range(first, last) => {
+                folded.push(item);
+                if first.is_ascii_lowercase() && last.is_ascii_lowercase() {
+                    let (first, last) = (first.to_ascii_uppercase(), last.to_ascii_uppercase());
+                    folded.push(ClassItem::Range(first, last));
+                } else if first.is_ascii_uppercase() && last.is_ascii_uppercase() {
+                    let (first, last) = (first.to_ascii_lowercase(), last.to_ascii_lowercase());
+                    folded.push(ClassItem::Range(first, last));
+                }
+            }
+            ClassItem::Posix(PosixClass::Lower | PosixClass::Upper) => {
+                folded.push(ClassItem::Posix(PosixClass::Lower));
+                folded.push(ClassItem::Posix(PosixClass::Upper));
+            }
+            ClassItem::Posix(_) => folded.push(item),
+        }
     }
+    folded
 }
 
-fn match_literal(literal: &str, input_chars: &mut Peekable<Chars>) -> bool {
-    for lit_char in literal.chars() {
-        match input_chars.next() {
-            Some(input_char) if input_char == lit_char => continue,
-            _ => return false,
+// Joins runs of adjacent literals, such as the ones either side of an escaped
+// char in `a\.b`, into a single literal
+fn merge_literals(patterns: Vec<Pattern>) -> Vec<Pattern> {
+    let mut merged: Vec<Pattern> = Vec::with_capacity(patterns.len());
+    for pattern in patterns {
+        match (merged.last_mut(), pattern) {
+            (Some(Pattern::Literal(last)), Pattern::Literal(literal)) => last.push_str(&literal),
+            (_, pattern) => merged.push(pattern),
         }
     }
-    true
+    merged
 }
 
-fn match_subpattern(
-    pattern: &Pattern,
-    input_chars: &mut Peekable<Chars>,
-    captured_groups: &mut HashMap<usize, String>,
-    current_group: Option<usize>,
-) -> bool {
-    let mut input_clone = input_chars.clone();
-    let matched = match pattern {
-        Pattern::Literal(ref literal) => match_literal(literal, &mut input_clone),
-        Pattern::Digit | Pattern::Alphanumeric | Pattern::AnyChar | Pattern::CharGroup(_, _) => {
-            match_class(pattern, &mut input_clone)
+// Moves the literal prefix shared by every branch of an alternation in front
+// of it, so `cat|car|can` becomes `ca(?:t|r|n)` and matches `ca` only once.
+// Branches are still tried in the same order, so matches don't change.
+fn factor_alternations(patterns: Vec<Pattern>) -> Vec<Pattern> {
+    patterns.into_iter().flat_map(factor_pattern).collect()
+}
+
+fn factor_pattern(pattern: Pattern) -> Vec<Pattern> {
+    let factor_boxed = |pattern: Box<Pattern>| {
+        let mut factored = factor_pattern(*pattern);
+        Box::new(match factored.len() {
+            1 => factored.remove(0),
+            _ => Pattern::Group(factored, None),
+        })
+    };
+    let pattern = match pattern {
+        Pattern::Alternation(alternatives) => {
+            let alternatives = alternatives.into_iter().flat_map(factor_pattern).collect();
+            return factor_prefix(alternatives);
         }
-        Pattern::Group(ref subpatterns) => {
-            let group_num = captured_groups.len() + 1;
-            if match_from_current_position(
-                &mut input_clone,
-                subpatterns,
-                false,
-                captured_groups,
-            ) {
-                let captured = extract_captured(input_chars, &input_clone);
-                captured_groups.insert(group_num, captured);
-                true
-            } else {
-                false
-            }
-        }
-        Pattern::Alternation(ref alternatives) => {
-            #[allow(unused_assignments)] // Suppress the unused_assignments warning for this block
-            {
-                for alternative in alternatives {
-                    let mut clone = input_clone.clone();
-                    let mut clone_captured = captured_groups.clone();
-                    if match_subpattern(
-                        alternative,
-                        &mut clone,
-                        &mut clone_captured,
-                        current_group,
-                    ) {
-                        // If a match is found, update the input and captured groups
-                        input_clone = clone;
-                        *captured_groups = clone_captured;
-                        return true;
-                    }
-                }
-                false
-            }
+        Pattern::Group(subpatterns, index) => Pattern::Group(factor_alternations(subpatterns), index),
+        Pattern::Lookahead { inner, negate } => {
+            Pattern::Lookahead { inner: factor_alternations(inner), negate }
         }
-        
-        Pattern::BackReference(group_num) => {
-            if let Some(captured) = captured_groups.get(group_num) {
-                let mut temp_input = input_clone.clone();
-                if match_literal(captured, &mut temp_input) {
-                    *input_chars = temp_input;
-                    return true;
-                } else {
-                    return false;
-                }
-            } else {
-                return false;
-            }
+        Pattern::Lookbehind { inner, negate, width } => {
+            Pattern::Lookbehind { inner: factor_alternations(inner), negate, width }
+        }
+        Pattern::OneOrMore { pattern, lazy, possessive } => {
+            Pattern::OneOrMore { pattern: factor_boxed(pattern), lazy, possessive }
+        }
+        Pattern::ZeroOrMore { pattern, lazy, possessive } => {
+            Pattern::ZeroOrMore { pattern: factor_boxed(pattern), lazy, possessive }
+        }
+        Pattern::ZeroOrOne { pattern, lazy, possessive } => {
+            Pattern::ZeroOrOne { pattern: factor_boxed(pattern), lazy, possessive }
+        }
+        Pattern::Repeat { pattern, min, max, lazy, possessive } => {
+            Pattern::Repeat { pattern: factor_boxed(pattern), min, max, lazy, possessive }
         }
-        _ => false, 
+        pattern => pattern,
     };
-    if matched {
-        *input_chars = input_clone;
-    }
-    matched
+    vec![pattern]
 }
 
-fn match_from_current_position(
-    input_chars: &mut Peekable<Chars>,
-    patterns: &[Pattern],
-    is_start: bool,
-    captured_groups: &mut HashMap<usize, String>,
-) -> bool {
-    let mut input_clone = input_chars.clone();
-    println!("Attempting to match from current position...");
-    for (i, pattern) in patterns.iter().enumerate() {
-        match pattern {
-            Pattern::Literal(ref literal) => {
-                println!("Matching Literal: '{}'", literal);
-                if !match_literal(literal, &mut input_clone) {
-                    println!("Literal '{}' did not match.", literal);
-                    return false;
-                }
-            }
-            Pattern::Start => {
-                println!("Matching Start Anchor");
-                if i != 0 || !is_start {
-                    println!("Start anchor not at the beginning.");
-                    return false;
-                }
-            }
-            Pattern::End => {
-                println!("Matching End Anchor");
-                if i != patterns.len() - 1 || input_clone.peek().is_some() {
-                    println!("End anchor does not match.");
-                    return false;
-                }
-            }
-            Pattern::OneOrMore(ref subpattern) => {
-                println!("Matching OneOrMore for pattern: {:?}", subpattern);
-                if !match_subpattern(
-                    subpattern,
-                    &mut input_clone,
-                    captured_groups,
-                    None,
-                ) {
-                    println!("OneOrMore subpattern did not match at least once.");
-                    return false;
+// The patterns matching the same as an alternation of `alternatives`, with
+// the literal prefix of every branch, if they share one, matched first
+fn factor_prefix(alternatives: Vec<Pattern>) -> Vec<Pattern> {
+    let prefix = match shared_literal_prefix(&alternatives) {
+        Some(prefix) if !prefix.is_empty() => prefix.to_string(),
+        _ => return vec![Pattern::Alternation(alternatives)],
+    };
+    let alternatives = alternatives
+        .into_iter()
+        .map(|alternative| match alternative {
+            Pattern::Group(mut subpatterns, None) => {
+                if let Some(Pattern::Literal(literal)) = subpatterns.first_mut() {
+                    literal.drain(..prefix.len());
+                    if literal.is_empty() {
+                        subpatterns.remove(0);
+                    }
                 }
-                while match_subpattern(
-                    subpattern,
-                    &mut input_clone,
-                    captured_groups,
-                    None,
-                ) {
-                    println!("OneOrMore subpattern matched another instance.");
-                }
-            }
-            Pattern::ZeroOrOne(ref subpattern) => {
-                println!("Matching ZeroOrOne for pattern: {:?}", subpattern);
-                if match_subpattern(
-                    subpattern,
-                    &mut input_clone,
-                    captured_groups,
-                    None,
-                ) {
-                    println!("ZeroOrOne subpattern matched once.");
+                if subpatterns.is_empty() {
+                    Pattern::Empty
                 } else {
-                    println!("ZeroOrOne subpattern did not match; proceeding without it.");
-                }
-            }
-            Pattern::Group(ref subpatterns) => {
-                println!("Matching Group");
-                if !match_from_current_position(
-                    &mut input_clone,
-                    subpatterns,
-                    false,
-                    captured_groups,
-                ) {
-                    println!("Group did not match.");
-                    return false;
+                    Pattern::Group(subpatterns, None)
                 }
             }
-            Pattern::Alternation(ref alternatives) => {
-                println!("Matching Alternation: {:?}", alternatives);
-                let mut alternation_matched = false;
-                for alternative in alternatives {
-                    let mut clone = input_clone.clone();
-                    let mut clone_captured = captured_groups.clone();
-                    if match_subpattern(
-                        alternative,
-                        &mut clone,
-                        &mut clone_captured,
-                        None,
-                    ) {
-                        input_clone = clone;
-                        *captured_groups = clone_captured;
-                        alternation_matched = true;
-                        println!("Alternation alternative {:?} matched.", alternative);
-                        break;
-                    }
-                }
-                if !alternation_matched {
-                    println!("No alternation alternatives matched.");
-                    return false;
-                }
+            alternative => alternative,
+        })
+        .collect();
+    vec![
+        Pattern::Literal(prefix),
+        Pattern::Group(vec![Pattern::Alternation(alternatives)], None),
+    ]
+}
+
+// The longest literal every branch starts with, if there are several and each
+// is a sequence starting with a literal
+fn shared_literal_prefix(alternatives: &[Pattern]) -> Option<&str> {
+    if alternatives.len() < 2 {
+        return None;
+    }
+    let mut prefix: Option<&str> = None;
+    for alternative in alternatives {
+        let Pattern::Group(subpatterns, None) = alternative else {
+            return None;
+        };
+        let Some(Pattern::Literal(literal)) = subpatterns.first() else {
+            return None;
+        };
+        prefix = Some(match prefix {
+            None => literal,
+            Some(prefix) => {
+                let len = prefix
+                    .char_indices()
+                    .zip(literal.chars())
+                    .find(|&((_, a), b)| a != b)
+                    .map_or(prefix.len().min(literal.len()), |((i, _), _)| i);
+                &prefix[..len]
             }
-            Pattern::BackReference(group_num) => {
-                println!("Matching BackReference: \\{}", group_num);
-                if !match_subpattern(
-                    pattern,
-                    &mut input_clone,
-                    captured_groups,
-                    None,
-                ) {
-                    println!("BackReference \\{} did not match.", group_num);
-                    return false;
-                }
+        });
+    }
+    prefix
+}
+
+// Reads the rest of a range such as `a-z` if `first` starts one, or else
+// returns `first` alone. A `-` before the closing `]` is a plain member.
+fn take_range(chars: &mut Peekable<Chars>, first: char) -> Result<ClassItem, ParseError> {
+    let mut lookahead = chars.clone();
+    if lookahead.next() != Some('-') {
+        return Ok(ClassItem::Char(first));
+    }
+    match lookahead.next() {
+        Some(last) if last != ']' => {
+            *chars = lookahead;
+            if last < first {
+                return Err(ParseError::InvalidRange(first, last));
             }
-            _ => {
-                println!("Matching Class Pattern: {:?}", pattern);
-                if !match_class(pattern, &mut input_clone) {
-                    println!("Class pattern did not match.");
-                    return false;
-                }
+            Ok(ClassItem::Range(first, last))
+        }
+        _ => Ok(ClassItem::Char(first)),
+    }
+}
+
+// Parses the rest of a `[:name:]` class after its opening `[:`
+fn parse_class_name(chars: &mut Peekable<Chars>) -> Result<PosixClass, ParseError> {
+    let mut name = String::new();
+    loop {
+        match chars.next() {
+            Some(':') if chars.peek() == Some(&']') => {
+                chars.next();
+                break;
             }
+            Some(c) => name.push(c),
+            None => return Err(ParseError::UnterminatedClass),
         }
     }
-    *input_chars = input_clone;
-    println!("Pattern matched successfully.");
-    true
+    PosixClass::from_name(&name).ok_or(ParseError::InvalidClassName(name))
 }
 
-fn extract_captured(before: &Peekable<Chars>, after: &Peekable<Chars>) -> String {
-    let before_str: String = before.clone().collect();
-    let after_str: String = after.clone().collect();
+// Takes the element a quantifier applies to: the last character of the pending
+// literal, or otherwise the last parsed pattern
+fn take_quantified(patterns: &mut Vec<Pattern>, literal_buffer: &mut String) -> Option<Pattern> {
+    match literal_buffer.pop() {
+        Some(last_char) => {
+            if !literal_buffer.is_empty() {
+                patterns.push(Pattern::Literal(literal_buffer.clone()));
+                literal_buffer.clear();
+            }
+            Some(Pattern::Literal(last_char.to_string()))
+        }
+        None => patterns.pop(),
+    }
+}
 
-    if before_str.len() >= after_str.len() {
-        let captured_len = before_str.len() - after_str.len();
-        before_str[..captured_len].to_string()
+// Consumes `marker` if it comes next. A `?` directly after a quantifier makes
+// it lazy and a `+` makes it possessive.
+fn take_marker(chars: &mut Peekable<Chars>, marker: char) -> bool {
+    if chars.peek() == Some(&marker) {
+        chars.next();
+        true
     } else {
-        String::new()
+        false
     }
 }
 
-pub fn match_pattern(input_line: &str, pattern_str: &str) -> bool {
-    println!("Input: '{}', Pattern: '{}'", input_line, pattern_str);
-    let patterns = parse_pattern(pattern_str);
-    let mut input_chars = input_line.chars().peekable();
-    let mut captured_groups: HashMap<usize, String> = HashMap::new();
+// Fails if another quantifier comes straight after a quantifier and its marker,
+// as in `a**`, `a+?+` or `a{2}{3}`, rather than quantifying the quantifier
+fn reject_stacked_quantifier(chars: &Peekable<Chars>) -> Result<(), ParseError> {
+    let mut lookahead = chars.clone();
+    let stacked = match lookahead.next() {
+        Some('+' | '*' | '?') => true,
+        Some('{') => parse_repeat_bounds(&mut lookahead).is_some(),
+        _ => false,
+    };
+    if stacked {
+        return Err(ParseError::InvalidQuantifier);
+    }
+    Ok(())
+}
 
-    let starts_with_anchor = matches!(patterns.first(), Some(Pattern::Start));
-    let ends_with_anchor = matches!(patterns.last(), Some(Pattern::End));
+// Parses the rest of a `{n}`, `{n,}` or `{n,m}` repetition after the opening
+// brace. As in GNU grep, a missing `n` before the comma means zero, so `{,m}`
+// is `{0,m}`. Bounds with `m` below `n` are returned for the caller to reject.
+fn parse_repeat_bounds(chars: &mut Peekable<Chars>) -> Option<(usize, Option<usize>)> {
+    let min = if chars.peek() == Some(&',') { 0 } else { parse_number(chars)? };
+    match chars.next()? {
+        '}' => Some((min, Some(min))),
+        ',' => {
+            if chars.peek() == Some(&'}') {
+                chars.next();
+                return Some((min, None));
+            }
+            let max = parse_number(chars)?;
+            if chars.next()? != '}' {
+                return None;
+            }
+            Some((min, Some(max)))
+        }
+        _ => None,
+    }
+}
 
-    if starts_with_anchor && ends_with_anchor {
-        println!("Pattern has both Start and End anchors.");
-        return match_from_current_position(
-            &mut input_chars,
-            &patterns,
-            true,
-            &mut captured_groups,
-        );
-    } else if starts_with_anchor {
-        println!("Pattern has Start anchor.");
-        return match_from_current_position(
-            &mut input_chars,
-            &patterns,
-            true,
-            &mut captured_groups,
-        );
-    } else if ends_with_anchor {
-        println!("Pattern has End anchor.");
-        while input_chars.peek().is_some() {
-            let mut clone = input_chars.clone();
-            let mut clone_captured = captured_groups.clone();
-            if match_from_current_position(
-                &mut clone,
-                &patterns,
-                false,
-                &mut clone_captured,
-            ) && clone.peek().is_none()
-            {
-                println!("Pattern matched with End anchor.");
-                return true;
+// Reads the name of a `(?<name>...)` group after its `<`, through the closing
+// `>`. Names are made of word characters and can't start with a digit or
+// repeat an earlier group's name.
+fn parse_group_name(
+    chars: &mut Peekable<Chars>,
+    group_names: &[Option<String>],
+) -> Result<String, ParseError> {
+    let mut name = String::new();
+    loop {
+        match chars.next() {
+            Some('>') => break,
+            Some(c) => name.push(c),
+            None => return Err(ParseError::InvalidGroupName(name)),
+        }
+    }
+    let valid = name.chars().next().is_some_and(|c| !c.is_ascii_digit())
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+    if !valid {
+        return Err(ParseError::InvalidGroupName(name));
+    }
+    if group_names.iter().flatten().any(|existing| *existing == name) {
+        return Err(ParseError::DuplicateGroupName(name));
+    }
+    Ok(name)
+}
+
+// Reads the two hex digits of a `\xHH` escape, giving the char with that code
+fn parse_hex_escape(chars: &mut Peekable<Chars>) -> Result<char, ParseError> {
+    let digits: String = chars.by_ref().take(2).collect();
+    if digits.len() != 2 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ParseError::InvalidHexEscape);
+    }
+    let code = u8::from_str_radix(&digits, 16).map_err(|_| ParseError::InvalidHexEscape)?;
+    Ok(char::from(code))
+}
+
+// Reads the `{...}` of a `\u{...}` escape, giving the char with that codepoint
+fn parse_unicode_escape(chars: &mut Peekable<Chars>) -> Result<char, ParseError> {
+    if chars.next() != Some('{') {
+        return Err(ParseError::InvalidUnicodeEscape);
+    }
+    let mut digits = String::new();
+    loop {
+        match chars.next() {
+            Some('}') => break,
+            Some(c) if c.is_ascii_hexdigit() && digits.len() < 6 => digits.push(c),
+            _ => return Err(ParseError::InvalidUnicodeEscape),
+        }
+    }
+    u32::from_str_radix(&digits, 16)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or(ParseError::InvalidUnicodeEscape)
+}
+
+fn parse_number(chars: &mut Peekable<Chars>) -> Option<usize> {
+    let mut digits = String::new();
+    while let Some(&c) = chars.peek() {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        digits.push(c);
+        chars.next();
+    }
+    digits.parse().ok()
+}
+
+// Collects a group's body up to its matching parenthesis, skipping over escaped
+// characters, `\Q...\E` spans and bracket expressions
+fn take_group_body(chars: &mut Peekable<Chars>) -> Result<String, ParseError> {
+    let mut body = String::new();
+    let mut depth = 1;
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(body);
+                }
+            }
+            '\\' => {
+                body.push(c);
+                if let Some(escaped) = chars.next() {
+                    body.push(escaped);
+                    if escaped == 'Q' {
+                        copy_quoted_span(chars, &mut body);
+                    }
+                }
+                continue;
+            }
+            '[' => {
+                body.push(c);
+                copy_bracket_expression(chars, &mut body);
+                continue;
             }
-            input_chars.next();
+            _ => {}
         }
-    } else {
-        println!("Pattern has no anchors. Searching for pattern anywhere in the input.");
-        while input_chars.peek().is_some() {
-            let mut clone = input_chars.clone();
-            let mut clone_captured = captured_groups.clone();
-            if match_from_current_position(
-                &mut clone,
-                &patterns,
-                false,
-                &mut clone_captured,
-            ) {
-                println!("Pattern matched.");
-                return true;
+        body.push(c);
+    }
+    Err(ParseError::UnmatchedParen)
+}
+
+// Splits a pattern or the body of a group on its top-level `|` into an
+// alternation of whole sequences, so `|` binds more loosely than anything else
+// and `ab|cd` is `ab` or `cd`. Each alternative is matched with the patterns
+// that follow the group as its continuation, so in `(ab|cd)ef` both `abef` and
+// `cdef` must match in full, and a later failure retries the next alternative.
+// Without a top-level `|` the sequence is parsed as it is.
+fn parse_alternatives(
+    pattern: &str,
+    group_names: &mut Vec<Option<String>>,
+    flags: Flags,
+) -> Result<Vec<Pattern>, ParseError> {
+    // Flags set at the very start apply to every alternative
+    if let Some(after) = pattern.strip_prefix("(?") {
+        let mut chars = after.chars().peekable();
+        if matches!(chars.peek(), Some('i' | 's')) {
+            if let (flags, false) = parse_flags(&mut chars, flags)? {
+                return parse_alternatives(&chars.collect::<String>(), group_names, flags);
             }
-            input_chars.next();
         }
     }
 
-    println!("Pattern did not match.");
-    false
+    let mut alternatives = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => {
+                depth += 1;
+                current.push(c);
+            }
+            ')' => {
+                depth -= 1;
+                current.push(c);
+            }
+            '\\' => {
+                current.push(c);
+                if let Some(escaped) = chars.next() {
+                    current.push(escaped);
+                    if escaped == 'Q' {
+                        copy_quoted_span(&mut chars, &mut current);
+                    }
+                }
+            }
+            '[' => {
+                current.push(c);
+                copy_bracket_expression(&mut chars, &mut current);
+            }
+            '|' if depth == 0 => {
+                alternatives.push(parse_alternative(&current, group_names, flags)?);
+                current.clear();
+            }
+            _ => {
+                current.push(c);
+            }
+        }
+    }
+
+    if alternatives.is_empty() {
+        return parse_sequence(&current, group_names, flags);
+    }
+    alternatives.push(parse_alternative(&current, group_names, flags)?);
+
+    Ok(vec![Pattern::Alternation(alternatives)])
+}
+
+// Parses one branch of an alternation. An empty branch, as in `(a|)`, matches
+// the empty string.
+fn parse_alternative(
+    alternative: &str,
+    group_names: &mut Vec<Option<String>>,
+    flags: Flags,
+) -> Result<Pattern, ParseError> {
+    if alternative.is_empty() {
+        return Ok(Pattern::Empty);
+    }
+    Ok(Pattern::Group(parse_sequence(alternative, group_names, flags)?, None))
+}
+
+fn match_class(pattern: &Pattern, c: char, options: RegexOptions) -> bool {
+    match pattern {
+        Pattern::Digit => c.is_ascii_digit(),
+        Pattern::Alphanumeric if options.ascii => c.is_ascii_alphanumeric() || c == '_',
+        Pattern::Alphanumeric => c.is_alphanumeric() || c == '_',
+        Pattern::AnyChar => c != '\n' || options.dotall,
+        Pattern::CharGroup(items, is_negative) => {
+            let in_group = items.iter().any(|item| match *item {
+                ClassItem::Char(member) => member == c,
+                ClassItem::Range(first, last) => (first..=last).contains(&c),
+                ClassItem::Posix(class) => class.matches(c),
+            });
+            in_group != *is_negative
+        }
+        _ => false,
+    }
+}
+
+// Whether `c` belongs to the grapheme cluster of the char before it: a
+// combining mark, variation selector, emoji skin tone modifier or zero width
+// joiner. This covers the common clusters without the full Unicode tables.
+fn extends_grapheme(c: char) -> bool {
+    matches!(
+        c,
+        '\u{300}'..='\u{36f}'
+            | '\u{1ab0}'..='\u{1aff}'
+            | '\u{1dc0}'..='\u{1dff}'
+            | '\u{200d}'
+            | '\u{20d0}'..='\u{20ff}'
+            | '\u{fe00}'..='\u{fe0f}'
+            | '\u{fe20}'..='\u{fe2f}'
+            | '\u{1f3fb}'..='\u{1f3ff}'
+            | '\u{e0100}'..='\u{e01ef}'
+    )
+}
+
+// Literals and single-character classes match in at most one way
+fn is_simple(pattern: &Pattern) -> bool {
+    matches!(
+        pattern,
+        Pattern::Literal(_)
+            | Pattern::Digit
+            | Pattern::Alphanumeric
+            | Pattern::AnyChar
+            | Pattern::CharGroup(_, _)
+    )
+}
+
+// Returns the repeated pattern, minimum and maximum count and laziness of a quantifier
+fn repetition_bounds(pattern: &Pattern) -> Option<(&Pattern, usize, Option<usize>, bool)> {
+    match pattern {
+        Pattern::OneOrMore { pattern, lazy, .. } => Some((pattern, 1, None, *lazy)),
+        Pattern::ZeroOrMore { pattern, lazy, .. } => Some((pattern, 0, None, *lazy)),
+        Pattern::ZeroOrOne { pattern, lazy, .. } => Some((pattern, 0, Some(1), *lazy)),
+        Pattern::Repeat { pattern, min, max, lazy, .. } => Some((pattern, *min, *max, *lazy)),
+        _ => None,
+    }
+}
+
+// Whether the patterns open with an unbounded repeat of `.`, and if so
+// whether that `.` also matches newlines. Grapheme mode is left out, as its
+// `.` steps over whole clusters and can't stop inside one.
+fn leading_dot_star(patterns: &[Pattern], options: RegexOptions) -> Option<bool> {
+    if options.grapheme {
+        return None;
+    }
+    match repetition_bounds(patterns.first()?)? {
+        (Pattern::AnyChar, _, None, _) => Some(options.dotall),
+        (Pattern::CharGroup(items, true), _, None, _) if items.is_empty() => Some(true),
+        _ => None,
+    }
+}
+
+fn is_possessive(pattern: &Pattern) -> bool {
+    matches!(
+        pattern,
+        Pattern::OneOrMore { possessive: true, .. }
+            | Pattern::ZeroOrMore { possessive: true, .. }
+            | Pattern::ZeroOrOne { possessive: true, .. }
+            | Pattern::Repeat { possessive: true, .. }
+    )
+}
+
+// Start and end byte offsets of a match or capture
+type Span = (usize, usize);
+
+// Spans captured so far, indexed by group number minus one
+type GroupSpans = [Option<Span>];
+
+// The span of a whole match followed by the spans of its capture groups
+type Found = (Span, Vec<Option<Span>>);
+
+// The rest of a match, called with the byte offset reached and the captures so
+// far once a pattern has matched. Returns whether the overall match succeeded.
+type Continuation<'n> = dyn FnMut(usize, &mut GroupSpans) -> bool + 'n;
+
+// Backtracking matcher over a single input. Each pattern is matched in every way
+// it can be, in order of preference, handing the offset it reached to a
+// continuation until one of them leads to an overall match. Positions are byte
+// offsets into the input, so saving and restoring them is free.
+struct Matcher<'a> {
+    input: &'a str,
+    group_count: usize,
+    // Whether capturing groups record their spans; without it every group is
+    // matched as if non-capturing
+    record_groups: bool,
+    options: RegexOptions,
+    // Patterns tried from the current start, checked against
+    // `options.step_limit`
+    steps: Cell<usize>,
+    // Every pattern tried so far, in order, kept only for `Regex::trace`
+    #[cfg(feature = "trace")]
+    trace: Option<RefCell<Vec<TraceEvent>>>,
+}
+
+impl<'a> Matcher<'a> {
+    // Matches `patterns` starting exactly at `start`, returning the end offset
+    // and group spans of the match chosen by `semantics`
+    fn match_here(
+        &self,
+        patterns: &[Pattern],
+        start: usize,
+        semantics: MatchSemantics,
+    ) -> Option<(usize, Vec<Option<Span>>)> {
+        self.steps.set(0);
+        let mut best = None;
+        let mut group_spans = vec![None; self.group_count];
+        self.match_sequence(patterns, start, &mut group_spans, &mut |end, groups| {
+            match semantics {
+                MatchSemantics::LeftmostFirst => {
+                    best = Some((end, groups.to_vec()));
+                    true
+                }
+                MatchSemantics::LeftmostLongest => {
+                    if best.as_ref().map_or(true, |(best_end, _)| end > *best_end) {
+                        best = Some((end, groups.to_vec()));
+                    }
+                    // Keep backtracking so every possible match is seen
+                    false
+                }
+                MatchSemantics::LeftmostShortest => {
+                    if best.as_ref().map_or(true, |(best_end, _)| end < *best_end) {
+                        best = Some((end, groups.to_vec()));
+                    }
+                    // No match can be shorter than an empty one
+                    end == start
+                }
+            }
+        });
+        best
+    }
+
+    // Continues from `pos` if `found` reports that an assertion's inner pattern
+    // matched, or with `negate` that it didn't. Captures made by the assertion
+    // are kept only while the rest of the match succeeds.
+    fn match_assertion(
+        &self,
+        negate: bool,
+        pos: usize,
+        groups: &mut GroupSpans,
+        next: &mut Continuation<'_>,
+        found: impl FnOnce(&mut GroupSpans) -> bool,
+    ) -> bool {
+        let saved = groups.to_vec();
+        if found(groups) != negate && next(pos, groups) {
+            return true;
+        }
+        groups.copy_from_slice(&saved);
+        false
+    }
+
+    // Counts one pattern attempt, returning false once the step limit is passed
+    fn step(&self) -> bool {
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+        !self.limit_exceeded()
+    }
+
+    fn limit_exceeded(&self) -> bool {
+        self.options
+            .step_limit
+            .is_some_and(|limit| self.steps.get() > limit)
+    }
+
+    // Whether `^` matches at `pos`: at the start of the input or, with the
+    // `multiline` option, after a `\n`
+    fn at_line_start(&self, pos: usize) -> bool {
+        pos == 0 || (self.options.multiline && self.input[..pos].ends_with('\n'))
+    }
+
+    // Whether `$` matches at `pos`: at the end of the input or, with the
+    // `multiline` option, before a `\n`. A `\r` there is skipped first, so
+    // `$` also matches at the end of a line with a Windows line ending.
+    fn at_line_end(&self, pos: usize) -> bool {
+        let rest = &self.input[pos..];
+        let rest = rest.strip_prefix('\r').unwrap_or(rest);
+        rest.is_empty() || (self.options.multiline && rest.starts_with('\n'))
+    }
+
+    // Whether exactly one of the characters either side of `pos` is a word
+    // character, treating the ends of the input as non-word
+    fn at_word_boundary(&self, pos: usize) -> bool {
+        let is_word = |c: Option<char>| {
+            c.is_some_and(|c| match_class(&Pattern::Alphanumeric, c, self.options))
+        };
+        is_word(self.input[..pos].chars().next_back()) != is_word(self.input[pos..].chars().next())
+    }
+
+    // Records an attempt to match `pattern` at `pos` in `trace`, returning a
+    // continuation that marks it as matched once it hands on to `next`
+    #[cfg(feature = "trace")]
+    fn traced<'n>(
+        trace: &'n RefCell<Vec<TraceEvent>>,
+        pattern: &Pattern,
+        pos: usize,
+        next: &'n mut Continuation<'n>,
+    ) -> impl FnMut(usize, &mut GroupSpans) -> bool + 'n {
+        let index = trace.borrow().len();
+        trace.borrow_mut().push(TraceEvent {
+            pattern: pattern.to_string(),
+            offset: pos,
+            matched: false,
+        });
+        move |end, groups| {
+            trace.borrow_mut()[index].matched = true;
+            next(end, groups)
+        }
+    }
+
+    // Matches `captured` at `pos` with each of its chars in any case,
+    // returning the offset after it
+    fn match_caseless(&self, captured: &str, pos: usize) -> Option<usize> {
+        let mut end = pos;
+        for c in captured.chars() {
+            let next = self.input[end..].chars().next()?;
+            if !case_variants(c).contains(&next) {
+                return None;
+            }
+            end += next.len_utf8();
+        }
+        Some(end)
+    }
+
+    // Matches a literal or single-character class at `pos`, returning the
+    // offset after it
+    fn match_simple(&self, pattern: &Pattern, pos: usize) -> Option<usize> {
+        let rest = &self.input[pos..];
+        match pattern {
+            Pattern::Literal(ref literal) => rest.starts_with(literal.as_str()).then(|| pos + literal.len()),
+            _ => {
+                let c = rest.chars().next()?;
+                if !match_class(pattern, c, self.options) {
+                    return None;
+                }
+                let end = pos + c.len_utf8();
+                Some(if self.options.grapheme { self.grapheme_end(end) } else { end })
+            }
+        }
+    }
+
+    // The end of the grapheme cluster whose first char ends at `pos`, taking in
+    // the chars after it that extend it
+    fn grapheme_end(&self, pos: usize) -> usize {
+        let mut end = pos;
+        // `\r\n` is a single cluster
+        let mut joined = self.input[..pos].ends_with('\r') && self.input[pos..].starts_with('\n');
+        for c in self.input[pos..].chars() {
+            if !joined && !extends_grapheme(c) {
+                break;
+            }
+            // A zero width joiner joins the next char to the cluster too
+            joined = c == '\u{200d}';
+            end += c.len_utf8();
+        }
+        end
+    }
+
+    fn match_sequence(
+        &self,
+        patterns: &[Pattern],
+        pos: usize,
+        groups: &mut GroupSpans,
+        next: &mut Continuation<'_>,
+    ) -> bool {
+        match patterns.split_first() {
+            None => next(pos, groups),
+            Some((pattern, rest)) => self.match_pattern(pattern, pos, groups, &mut |pos, groups| {
+                self.match_sequence(rest, pos, groups, next)
+            }),
+        }
+    }
+
+    fn match_pattern(
+        &self,
+        pattern: &Pattern,
+        pos: usize,
+        groups: &mut GroupSpans,
+        next: &mut Continuation<'_>,
+    ) -> bool {
+        // Once over the limit every attempt fails, unwinding the search
+        if !self.step() {
+            return false;
+        }
+        #[cfg(feature = "trace")]
+        if let Some(trace) = &self.trace {
+            let next = &mut Self::traced(trace, pattern, pos, next);
+            return self.match_element(pattern, pos, groups, next);
+        }
+        self.match_element(pattern, pos, groups, next)
+    }
+
+    fn match_element(
+        &self,
+        pattern: &Pattern,
+        pos: usize,
+        groups: &mut GroupSpans,
+        next: &mut Continuation<'_>,
+    ) -> bool {
+        if let Some((subpattern, min, max, lazy)) = repetition_bounds(pattern) {
+            let repeat = |groups: &mut GroupSpans, next: &mut Continuation<'_>| {
+                if is_simple(subpattern) {
+                    self.match_simple_repetition(subpattern, (min, max, lazy), pos, groups, next)
+                } else {
+                    self.match_repetition(subpattern, (min, max, lazy), 0, pos, groups, next)
+                }
+            };
+            if !is_possessive(pattern) {
+                return repeat(groups, next);
+            }
+            // Commit to the repetitions a greedy quantifier would take first,
+            // giving none back if the rest of the match fails
+            let saved = groups.to_vec();
+            let mut committed = None;
+            repeat(groups, &mut |end, _| {
+                committed = Some(end);
+                true
+            });
+            if committed.is_some_and(|end| next(end, groups)) {
+                return true;
+            }
+            groups.copy_from_slice(&saved);
+            return false;
+        }
+        match pattern {
+            Pattern::Empty => next(pos, groups),
+            Pattern::Start => self.at_line_start(pos) && next(pos, groups),
+            Pattern::End => self.at_line_end(pos) && next(pos, groups),
+            Pattern::InputStart => pos == 0 && next(pos, groups),
+            Pattern::InputEnd => pos == self.input.len() && next(pos, groups),
+            Pattern::WordBoundary => self.at_word_boundary(pos) && next(pos, groups),
+            Pattern::NonWordBoundary => !self.at_word_boundary(pos) && next(pos, groups),
+            Pattern::Group(ref subpatterns, group_index) => {
+                self.match_sequence(subpatterns, pos, groups, &mut |end, groups| {
+                    let slot = match group_index {
+                        Some(group_index) if self.record_groups => group_index - 1,
+                        _ => return next(end, groups),
+                    };
+                    let previous = groups[slot].replace((pos, end));
+                    if next(end, groups) {
+                        return true;
+                    }
+                    // Undo the capture before backtracking into the group
+                    groups[slot] = previous;
+                    false
+                })
+            }
+            Pattern::Alternation(ref alternatives) => alternatives
+                .iter()
+                .any(|alternative| self.match_pattern(alternative, pos, groups, next)),
+            Pattern::Lookahead { ref inner, negate } => {
+                self.match_assertion(*negate, pos, groups, next, |groups| {
+                    self.match_sequence(inner, pos, groups, &mut |_, _| true)
+                })
+            }
+            Pattern::Lookbehind { ref inner, negate, width } => {
+                // Step back `width` chars, failing to match if there aren't
+                // that many before `pos`
+                let start = match width {
+                    0 => Some(pos),
+                    _ => self.input[..pos].char_indices().nth_back(width - 1).map(|(i, _)| i),
+                };
+                self.match_assertion(*negate, pos, groups, next, |groups| {
+                    start.is_some_and(|start| {
+                        self.match_sequence(inner, start, groups, &mut |end, _| end == pos)
+                    })
+                })
+            }
+            Pattern::BackReference(group_num, case_insensitive) => {
+                let span = group_num.checked_sub(1).and_then(|slot| groups.get(slot).copied());
+                match span.flatten() {
+                    Some((start, end)) if *case_insensitive => {
+                        match self.match_caseless(&self.input[start..end], pos) {
+                            Some(end) => next(end, groups),
+                            None => false,
+                        }
+                    }
+                    Some((start, end)) => {
+                        let captured = &self.input[start..end];
+                        self.input[pos..].starts_with(captured) && next(pos + captured.len(), groups)
+                    }
+                    None => false,
+                }
+            }
+            _ => match self.match_simple(pattern, pos) {
+                Some(end) => next(end, groups),
+                None => false,
+            },
+        }
+    }
+
+    // Matches `count` or more further repetitions of `subpattern` followed by
+    // the rest of the match. Greedy quantifiers try another repetition before
+    // stopping, lazy ones stop first.
+    fn match_repetition(
+        &self,
+        subpattern: &Pattern,
+        (min, max, lazy): (usize, Option<usize>, bool),
+        count: usize,
+        pos: usize,
+        groups: &mut GroupSpans,
+        next: &mut Continuation<'_>,
+    ) -> bool {
+        let can_stop = count >= min;
+        let can_repeat = max.map_or(true, |max| count < max);
+
+        if lazy && can_stop && next(pos, groups) {
+            return true;
+        }
+        if can_repeat {
+            let repeated = self.match_pattern(subpattern, pos, groups, &mut |end, groups| {
+                // A repetition that consumes nothing would repeat forever
+                if can_stop && end == pos {
+                    return false;
+                }
+                self.match_repetition(subpattern, (min, max, lazy), count + 1, end, groups, next)
+            });
+            if repeated {
+                return true;
+            }
+        }
+        !lazy && can_stop && next(pos, groups)
+    }
+
+    // Repetition of a pattern that can only match one way. The offset after
+    // each repetition is collected up front instead of recursing per
+    // repetition, so long runs don't grow the stack.
+    fn match_simple_repetition(
+        &self,
+        subpattern: &Pattern,
+        (min, max, lazy): (usize, Option<usize>, bool),
+        pos: usize,
+        groups: &mut GroupSpans,
+        next: &mut Continuation<'_>,
+    ) -> bool {
+        let mut ends = vec![pos];
+        while max.map_or(true, |max| ends.len() <= max) {
+            match self.match_simple(subpattern, ends[ends.len() - 1]) {
+                Some(end) => ends.push(end),
+                None => break,
+            }
+        }
+
+        let mut try_count = |count: usize| next(ends[count], groups);
+        if lazy {
+            (min..ends.len()).any(&mut try_count)
+        } else {
+            (min..ends.len()).rev().any(&mut try_count)
+        }
+    }
+}
+
+// Finds the capturing group with the given index
+fn find_group(patterns: &[Pattern], index: usize) -> Option<&Pattern> {
+    patterns.iter().find_map(|pattern| match pattern {
+        Pattern::Group(_, Some(group_index)) if *group_index == index => Some(pattern),
+        Pattern::Group(subpatterns, _) | Pattern::Alternation(subpatterns) => {
+            find_group(subpatterns, index)
+        }
+        _ => repetition_bounds(pattern)
+            .and_then(|(subpattern, ..)| find_group(std::slice::from_ref(subpattern), index)),
+    })
+}
+
+// Whether `predicate` holds for any pattern in the sequence, at any depth
+fn any_pattern(patterns: &[Pattern], predicate: &dyn Fn(&Pattern) -> bool) -> bool {
+    patterns.iter().any(|pattern| {
+        predicate(pattern)
+            || match pattern {
+                Pattern::Group(subpatterns, _) | Pattern::Alternation(subpatterns) => {
+                    any_pattern(subpatterns, predicate)
+                }
+                Pattern::Lookahead { inner, .. } | Pattern::Lookbehind { inner, .. } => {
+                    any_pattern(inner, predicate)
+                }
+                _ => repetition_bounds(pattern).is_some_and(|(subpattern, ..)| {
+                    any_pattern(std::slice::from_ref(subpattern), predicate)
+                }),
+            }
+    })
+}
+
+fn contains_backreference(patterns: &[Pattern]) -> bool {
+    any_pattern(patterns, &|pattern| matches!(pattern, Pattern::BackReference(..)))
+}
+
+// Whether any literal or bracket expression names a non-ASCII char
+fn contains_non_ascii(patterns: &[Pattern]) -> bool {
+    any_pattern(patterns, &|pattern| match pattern {
+        Pattern::Literal(literal) => !literal.is_ascii(),
+        Pattern::CharGroup(items, _) => items
+            .iter()
+            .any(|item| match *item {
+                ClassItem::Char(c) | ClassItem::Range(_, c) => !c.is_ascii(),
+                ClassItem::Posix(_) => false,
+            }),
+        _ => false,
+    })
+}
+
+// Minimum and maximum number of chars a sequence can match. Backreferences are
+// resolved against the groups in `root`; without it they are unbounded.
+fn sequence_length_bounds(patterns: &[Pattern], root: Option<&[Pattern]>) -> (usize, Option<usize>) {
+    patterns.iter().fold((0, Some(0)), |(min, max), pattern| {
+        let (pattern_min, pattern_max) = length_bounds(pattern, root);
+        let max = max.zip(pattern_max).and_then(|(max, pattern_max)| max.checked_add(pattern_max));
+        (min.saturating_add(pattern_min), max)
+    })
+}
+
+fn length_bounds(pattern: &Pattern, root: Option<&[Pattern]>) -> (usize, Option<usize>) {
+    if let Some((subpattern, min, max, _)) = repetition_bounds(pattern) {
+        let (sub_min, sub_max) = length_bounds(subpattern, root);
+        let max = match (max, sub_max) {
+            (Some(0), _) | (_, Some(0)) => Some(0),
+            (Some(max), Some(sub_max)) => max.checked_mul(sub_max),
+            _ => None,
+        };
+        return (sub_min.saturating_mul(min), max);
+    }
+    match pattern {
+        Pattern::Literal(literal) => {
+            let len = literal.chars().count();
+            (len, Some(len))
+        }
+        Pattern::Digit | Pattern::Alphanumeric | Pattern::AnyChar | Pattern::CharGroup(..) => {
+            (1, Some(1))
+        }
+        Pattern::Group(subpatterns, _) => sequence_length_bounds(subpatterns, root),
+        Pattern::Alternation(alternatives) => alternatives
+            .iter()
+            .map(|alternative| length_bounds(alternative, root))
+            .reduce(|(min_a, max_a), (min_b, max_b)| {
+                (min_a.min(min_b), max_a.zip(max_b).map(|(a, b)| a.max(b)))
+            })
+            .unwrap_or((0, Some(0))),
+        // The group is measured without resolving further backreferences, so
+        // a group that refers to itself can't recurse forever
+        Pattern::BackReference(index, _) => root
+            .and_then(|root| find_group(root, *index))
+            .map_or((0, None), |group| length_bounds(group, None)),
+        _ => (0, Some(0)),
+    }
+}
+
+// The longest literal that every match must contain, if any. Inputs without it
+// can be rejected without trying each start position.
+fn required_literal(patterns: &[Pattern]) -> Option<&str> {
+    patterns
+        .iter()
+        .filter_map(|pattern| match pattern {
+            Pattern::Literal(literal) => Some(literal.as_str()),
+            Pattern::Group(subpatterns, _) => required_literal(subpatterns),
+            Pattern::OneOrMore { pattern, .. } => required_literal(std::slice::from_ref(pattern)),
+            Pattern::Repeat { pattern, min, .. } if *min > 0 => {
+                required_literal(std::slice::from_ref(pattern))
+            }
+            _ => None,
+        })
+        .max_by_key(|literal| literal.len())
+}
+
+// The chars a match must start with, if there are only a few. Start positions
+// at any other char can be skipped without trying to match there.
+fn first_chars(patterns: &[Pattern]) -> Option<Vec<char>> {
+    for pattern in patterns {
+        match pattern {
+            // Boundaries consume nothing, so the next pattern starts the match
+            Pattern::WordBoundary | Pattern::NonWordBoundary => continue,
+            _ => return pattern_first_chars(pattern),
+        }
+    }
+    None
+}
+
+fn pattern_first_chars(pattern: &Pattern) -> Option<Vec<char>> {
+    match pattern {
+        Pattern::Literal(literal) => literal.chars().next().map(|c| vec![c]),
+        Pattern::CharGroup(items, false) => items
+            .iter()
+            .map(|item| match item {
+                ClassItem::Char(c) => Some(*c),
+                ClassItem::Range(..) | ClassItem::Posix(_) => None,
+            })
+            .collect(),
+        Pattern::Group(subpatterns, _) => first_chars(subpatterns),
+        Pattern::Alternation(alternatives) => {
+            let mut chars = Vec::new();
+            for alternative in alternatives {
+                for c in pattern_first_chars(alternative)? {
+                    if !chars.contains(&c) {
+                        chars.push(c);
+                    }
+                }
+            }
+            Some(chars)
+        }
+        _ => match repetition_bounds(pattern)? {
+            (subpattern, min, ..) if min > 0 => pattern_first_chars(subpattern),
+            _ => None,
+        },
+    }
+}
+
+// Whether `needle` occurs in `haystack`, checking for the rest of it only
+// where its first byte is found
+fn contains_bytes(haystack: &[u8], needle: &[u8]) -> bool {
+    let Some((&first, rest)) = needle.split_first() else {
+        return true;
+    };
+    let mut start = 0;
+    while let Some(offset) = haystack[start..].iter().position(|&byte| byte == first) {
+        start += offset + 1;
+        if haystack[start..].starts_with(rest) {
+            return true;
+        }
+    }
+    false
+}
+
+// Bytes read at a time by `Regex::is_match_reader`
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// A parsed pattern that can be matched against many inputs.
+#[derive(Debug, Clone)]
+pub struct Regex {
+    patterns: Vec<Pattern>,
+    // The name, if any, of each capturing group, shared with its captures
+    group_names: Arc<[Option<String>]>,
+    required: Option<String>,
+    first_chars: Option<Vec<char>>,
+    // Group spans are needed while matching, not just for captures
+    has_backreferences: bool,
+    options: RegexOptions,
+}
+
+/// An attempt to match one element of a pattern, recorded by [`Regex::trace`].
+#[cfg(feature = "trace")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEvent {
+    /// The element tried, as it would be written in a pattern.
+    pub pattern: String,
+    /// Byte offset in the input where it was tried.
+    pub offset: usize,
+    /// Whether it matched there, whether or not the rest of the pattern then
+    /// matched after it.
+    pub matched: bool,
+}
+
+/// Settings that change how a [`Regex`] matches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RegexOptions {
+    /// Restricts `\w` to `[A-Za-z0-9_]`. By default it also matches Unicode
+    /// letters and digits.
+    pub ascii: bool,
+    /// Only accepts matches with a word boundary at each end, as if the
+    /// pattern were wrapped in `\b...\b`.
+    pub word: bool,
+    /// Only accepts matches spanning the whole input, as if the pattern were
+    /// wrapped in `^...$`; see [`Regex::is_full_match`].
+    pub line: bool,
+    /// Abandons a search after this many pattern attempts at any one start
+    /// position, bounding the time spent on patterns like `(a+)+b` that
+    /// backtrack exponentially. Unlimited by default.
+    pub step_limit: Option<usize>,
+    /// Treats the whole pattern as a literal string to search for, with no
+    /// metacharacters.
+    pub fixed: bool,
+    /// Lets `.` match `\n` too, so a match can span lines of a multi-line
+    /// input.
+    pub dotall: bool,
+    /// Lets `^` and `$` also match just after and just before each `\n`, at
+    /// the start and end of every line of a multi-line input.
+    pub multiline: bool,
+    /// Matches letters in either case, as if the pattern started with `(?i)`.
+    pub case_insensitive: bool,
+    /// Lets `.`, `\d`, `\w` and bracket expressions match a whole grapheme
+    /// cluster, such as `e` followed by a combining accent, rather than a
+    /// single char. Only the first char of the cluster is tested against the
+    /// class. Lookbehinds still step back by chars.
+    pub grapheme: bool,
+}
+
+/// A single match of a pattern within an input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match<'t> {
+    input: &'t str,
+    start: usize,
+    end: usize,
+}
+
+/// The span of a match and the text of its capture groups.
+///
+/// Groups are stored as byte spans into the input. For patterns without
+/// groups only the span of the whole match is stored, so no group storage is
+/// allocated.
+#[derive(Debug, Clone)]
+pub struct Captures<'t> {
+    input: &'t str,
+    span: (usize, usize),
+    groups: Vec<Option<Span>>,
+    group_names: Arc<[Option<String>]>,
+}
+
+impl Regex {
+    /// Escapes every metacharacter in `literal`, giving a pattern that
+    /// matches exactly `literal`, so `a.b+c` becomes `a\.b\+c`.
+    pub fn escape(literal: &str) -> String {
+        Pattern::Literal(literal.to_string()).to_string()
+    }
+
+    /// Parses `pattern`, returning an error if it is malformed.
+    pub fn new(pattern: &str) -> Result<Regex, ParseError> {
+        Regex::with_options(pattern, RegexOptions::default())
+    }
+
+    /// Parses `pattern` to match according to `options`.
+    pub fn with_options(pattern: &str, options: RegexOptions) -> Result<Regex, ParseError> {
+        let mut group_names = Vec::new();
+        let flags = Flags {
+            case_insensitive: options.case_insensitive,
+            multiline: false,
+            dotall: false,
+        };
+        let mut patterns = if options.fixed {
+            merge_literals(apply_flags(vec![Pattern::Literal(pattern.to_string())], flags))
+        } else {
+            parse_alternatives(pattern, &mut group_names, flags)?
+        };
+        if options.word {
+            patterns = vec![
+                Pattern::WordBoundary,
+                Pattern::Group(patterns, None),
+                Pattern::WordBoundary,
+            ];
+        }
+        if options.line {
+            patterns = vec![Pattern::Start, Pattern::Group(patterns, None), Pattern::End];
+        }
+        let patterns = factor_alternations(patterns);
+        let required = required_literal(&patterns).map(str::to_string);
+        let first_chars = first_chars(&patterns);
+        let has_backreferences = contains_backreference(&patterns);
+        Ok(Regex {
+            patterns,
+            group_names: group_names.into(),
+            required,
+            first_chars,
+            has_backreferences,
+            options,
+        })
+    }
+
+    // A matcher over `input` that records group spans only if `capture` is set
+    // or backreferences need them
+    fn matcher<'t>(&self, input: &'t str, capture: bool) -> Matcher<'t> {
+        let record_groups = capture || self.has_backreferences;
+        Matcher {
+            input,
+            group_count: if record_groups { self.group_names.len() } else { 0 },
+            record_groups,
+            options: self.options,
+            steps: Cell::new(0),
+            #[cfg(feature = "trace")]
+            trace: None,
+        }
+    }
+
+    // Finds the leftmost match in `input` starting at or after byte offset
+    // `start`, returning its span and, if `capture` is set, the spans of its
+    // capture groups
+    fn try_search(
+        &self,
+        input: &str,
+        mut start: usize,
+        semantics: MatchSemantics,
+        capture: bool,
+    ) -> Result<Option<Found>, MatchError> {
+        // A lone literal needs no backtracking, only a substring search
+        if let [Pattern::Literal(literal)] = self.patterns.as_slice() {
+            return Ok(input[start..].find(literal.as_str()).map(|offset| {
+                let match_start = start + offset;
+                ((match_start, match_start + literal.len()), Vec::new())
+            }));
+        }
+
+        if let Some(required) = &self.required {
+            if !input[start..].contains(required.as_str()) {
+                return Ok(None);
+            }
+        }
+
+        let matcher = self.matcher(input, capture);
+        let match_at = |start| {
+            let found = matcher.match_here(&self.patterns, start, semantics);
+            if matcher.limit_exceeded() {
+                return Err(MatchError::ComplexityLimitExceeded);
+            }
+            Ok(found.map(|(end, group_spans)| ((start, end), group_spans)))
+        };
+
+        let anchored = match self.patterns.first() {
+            Some(Pattern::Start) => !self.options.multiline,
+            Some(Pattern::InputStart) => true,
+            _ => false,
+        };
+        if anchored {
+            if start > 0 {
+                return Ok(None);
+            }
+            return match_at(0);
+        }
+
+        // A match after a leading `.*` could have started earlier, with the
+        // `.*` taking the chars before it, so past the first position only
+        // line starts are tried, and none at all if `.` matches newlines
+        if let Some(dotall) = leading_dot_star(&self.patterns, self.options) {
+            if let Some(found) = match_at(start)? {
+                return Ok(Some(found));
+            }
+            if dotall {
+                return Ok(None);
+            }
+            for (offset, _) in input[start..].match_indices('\n') {
+                if let Some(found) = match_at(start + offset + 1)? {
+                    return Ok(Some(found));
+                }
+            }
+            return Ok(None);
+        }
+
+        // Every position is tried, including the end of the input, where
+        // patterns that can match the empty string still match
+        loop {
+            if let Some(first_chars) = &self.first_chars {
+                match input[start..].find(first_chars.as_slice()) {
+                    Some(offset) => start += offset,
+                    None => return Ok(None),
+                }
+            }
+            if let Some(found) = match_at(start)? {
+                return Ok(Some(found));
+            }
+            match input[start..].chars().next() {
+                Some(c) => start += c.len_utf8(),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    // Like `try_search`, but a search over the step limit finds nothing
+    fn search(
+        &self,
+        input: &str,
+        start: usize,
+        semantics: MatchSemantics,
+        capture: bool,
+    ) -> Option<Found> {
+        self.try_search(input, start, semantics, capture).ok().flatten()
+    }
+
+    /// Returns whether the pattern matches anywhere in `input`. A search
+    /// abandoned at the step limit counts as no match; use
+    /// [`Regex::try_is_match`] to tell the two apart.
+    pub fn is_match(&self, input: &str) -> bool {
+        self.search(input, 0, MatchSemantics::LeftmostFirst, false).is_some()
+    }
+
+    /// Returns whether the pattern matches anywhere in `input`, or an error if
+    /// the search passed the step limit.
+    pub fn try_is_match(&self, input: &str) -> Result<bool, MatchError> {
+        Ok(self.try_search(input, 0, MatchSemantics::LeftmostFirst, false)?.is_some())
+    }
+
+    /// Returns whether the pattern matches anywhere in `input`, which need not
+    /// be valid UTF-8.
+    ///
+    /// Only patterns made of ASCII chars can match bytes; any other pattern is
+    /// an error. Each byte outside ASCII is matched as U+FFFD, the replacement
+    /// character, so `.` and negated bracket expressions match it but no
+    /// literal, `\d` or `\w` does.
+    pub fn is_match_bytes(&self, input: &[u8]) -> Result<bool, MatchError> {
+        if contains_non_ascii(&self.patterns) {
+            return Err(MatchError::NonAsciiPattern);
+        }
+        // A lone literal is searched for in the bytes themselves, without
+        // copying them into a string first
+        if let [Pattern::Literal(literal)] = self.patterns.as_slice() {
+            return Ok(contains_bytes(input, literal.as_bytes()));
+        }
+        let text: String = input
+            .iter()
+            .map(|&byte| match byte {
+                0..=0x7f => char::from(byte),
+                _ => char::REPLACEMENT_CHARACTER,
+            })
+            .collect();
+        self.try_is_match(&text)
+    }
+
+    /// Returns whether the pattern matches anywhere in `input`, along with
+    /// every attempt to match an element of it, in the order they were made,
+    /// for working out why a pattern does or doesn't match.
+    ///
+    /// Every start position is tried in turn until one matches, without the
+    /// shortcuts [`Regex::is_match`] takes to skip positions that can't.
+    #[cfg(feature = "trace")]
+    pub fn trace(&self, input: &str) -> (bool, Vec<TraceEvent>) {
+        let matcher = Matcher {
+            trace: Some(RefCell::new(Vec::new())),
+            ..self.matcher(input, false)
+        };
+        let matched = input
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain([input.len()])
+            .any(|start| {
+                let found = matcher.match_here(&self.patterns, start, MatchSemantics::LeftmostFirst);
+                found.is_some()
+            });
+        (matched, matcher.trace.unwrap_or_default().into_inner())
+    }
+
+    /// Returns whether the pattern matches anywhere in the text read from
+    /// `reader`, stopping at the first match.
+    ///
+    /// A pattern that is a lone literal is searched for a chunk at a time, so
+    /// memory use stays bounded however long the input is, even on a single
+    /// line, and the input need not be valid UTF-8. Any other pattern needs
+    /// the whole input read into memory first, and fails with
+    /// [`io::ErrorKind::InvalidData`] if it isn't valid UTF-8.
+    pub fn is_match_reader<R: Read>(&self, mut reader: R) -> io::Result<bool> {
+        let [Pattern::Literal(literal)] = self.patterns.as_slice() else {
+            let mut input = String::new();
+            reader.read_to_string(&mut input)?;
+            return Ok(self.is_match(&input));
+        };
+        let literal = literal.as_bytes();
+        let mut chunk = vec![0; READ_CHUNK_SIZE];
+        let mut window = Vec::with_capacity(READ_CHUNK_SIZE + literal.len());
+        loop {
+            let read = match reader.read(&mut chunk) {
+                Ok(0) => return Ok(literal.is_empty()),
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            window.extend_from_slice(&chunk[..read]);
+            if contains_bytes(&window, literal) {
+                return Ok(true);
+            }
+            // Keep the end of the window, where a match continuing into the
+            // next chunk could start
+            let keep = (literal.len() - 1).min(window.len());
+            window.drain(..window.len() - keep);
+        }
+    }
+
+    /// Returns whether a match starts at the beginning of `input`, as if the
+    /// pattern began with `^`. The match need not reach the end of `input`.
+    pub fn is_match_at_start(&self, input: &str) -> bool {
+        let matcher = self.matcher(input, false);
+        matcher
+            .match_here(&self.patterns, 0, MatchSemantics::LeftmostFirst)
+            .is_some()
+    }
+
+    /// Returns the end offset of the shortest match starting at the beginning
+    /// of `input`, so for `a+` on `aaa` it is 1.
+    pub fn shortest_match(&self, input: &str) -> Option<usize> {
+        let matcher = self.matcher(input, false);
+        let (end, _) = matcher.match_here(&self.patterns, 0, MatchSemantics::LeftmostShortest)?;
+        Some(end)
+    }
+
+    /// Returns whether the whole of `input` matches, as if the pattern were
+    /// wrapped in `^...$`.
+    pub fn is_full_match(&self, input: &str) -> bool {
+        let matcher = self.matcher(input, false);
+        let mut group_spans = vec![None; matcher.group_count];
+        matcher.match_sequence(&self.patterns, 0, &mut group_spans, &mut |end, _| {
+            end == input.len()
+        })
+    }
+
+    /// Returns the minimum and, if bounded, maximum number of chars a match
+    /// can span. With [`RegexOptions::grapheme`] a pattern with a class has
+    /// no maximum, as each class can match a cluster of any length.
+    pub fn length_bounds(&self) -> (usize, Option<usize>) {
+        let (min, max) = sequence_length_bounds(&self.patterns, Some(&self.patterns));
+        let is_class = |pattern: &Pattern| {
+            is_simple(pattern) && !matches!(pattern, Pattern::Literal(_))
+        };
+        if self.options.grapheme && any_pattern(&self.patterns, &is_class) {
+            return (min, None);
+        }
+        (min, max)
+    }
+
+    /// Returns the leftmost match, preferring alternatives and repetition
+    /// counts in pattern order.
+    pub fn find<'t>(&self, input: &'t str) -> Option<Match<'t>> {
+        self.find_with(input, MatchSemantics::LeftmostFirst)
+    }
+
+    /// Returns the leftmost match starting at or after byte offset `start`,
+    /// without searching the input before it again. The input before `start`
+    /// still counts for `^`, `\b` and lookbehinds, so searching `aaa` for `^a`
+    /// from offset 1 finds nothing.
+    ///
+    /// Panics if `start` is past the end of `input` or not on a char boundary.
+    pub fn find_from<'t>(&self, input: &'t str, start: usize) -> Option<Match<'t>> {
+        assert!(
+            input.is_char_boundary(start),
+            "start offset {} is not a char boundary of the input",
+            start
+        );
+        let ((start, end), _) = self.search(input, start, MatchSemantics::LeftmostFirst, false)?;
+        Some(Match { input, start, end })
+    }
+
+    /// Returns the leftmost match, choosing among the matches starting there
+    /// according to `semantics`.
+    pub fn find_with<'t>(&self, input: &'t str, semantics: MatchSemantics) -> Option<Match<'t>> {
+        let ((start, end), _) = self.search(input, 0, semantics, false)?;
+        Some(Match { input, start, end })
+    }
+
+    /// Returns the leftmost match and its capture groups.
+    pub fn captures<'t>(&self, input: &'t str) -> Option<Captures<'t>> {
+        self.captures_at(input, 0)
+    }
+
+    fn captures_at<'t>(&self, input: &'t str, start: usize) -> Option<Captures<'t>> {
+        let (span, groups) = self.search(input, start, MatchSemantics::LeftmostFirst, true)?;
+        Some(Captures {
+            input,
+            span,
+            groups,
+            group_names: Arc::clone(&self.group_names),
+        })
+    }
+
+    /// Replaces the leftmost match with `replacement`, expanding `$N` to the
+    /// text of group N and `$$` to a literal `$`.
+    pub fn replace(&self, input: &str, replacement: &str) -> String {
+        let captures = match self.captures(input) {
+            Some(captures) => captures,
+            None => return input.to_string(),
+        };
+        let (start, end) = captures.span();
+        let mut result = String::with_capacity(input.len());
+        result.push_str(&input[..start]);
+        captures.expand(replacement, &mut result);
+        result.push_str(&input[end..]);
+        result
+    }
+
+    /// Returns the captures of every non-overlapping match, from left to
+    /// right. Groups are tracked afresh for each match.
+    pub fn captures_iter<'t>(&self, input: &'t str) -> Vec<Captures<'t>> {
+        self.captures_up_to(input, usize::MAX)
+    }
+
+    // The captures of the first `limit` non-overlapping matches
+    fn captures_up_to<'t>(&self, input: &'t str, limit: usize) -> Vec<Captures<'t>> {
+        let mut all = Vec::new();
+        let mut search_start = 0;
+        while all.len() < limit {
+            let Some(captures) = self.captures_at(input, search_start) else {
+                break;
+            };
+            let (start, end) = captures.span();
+            all.push(captures);
+            match next_search_start(input, start, end) {
+                Some(next) => search_start = next,
+                None => break,
+            }
+        }
+        all
+    }
+
+    /// Returns every non-overlapping match, from left to right.
+    pub fn find_all<'t>(&self, input: &'t str) -> Vec<Match<'t>> {
+        self.find_iter(input).collect()
+    }
+
+    /// Returns an iterator over every non-overlapping match, from left to
+    /// right. Each match is only searched for when the iterator is advanced,
+    /// so `find_iter(input).take(2)` stops after the second match.
+    pub fn find_iter<'r, 't>(&'r self, input: &'t str) -> Matches<'r, 't> {
+        self.find_iter_with(input, MatchSemantics::LeftmostFirst)
+    }
+
+    /// Returns an iterator over every non-overlapping match, choosing each
+    /// one among the matches at its start according to `semantics`.
+    pub fn find_iter_with<'r, 't>(
+        &'r self,
+        input: &'t str,
+        semantics: MatchSemantics,
+    ) -> Matches<'r, 't> {
+        Matches {
+            regex: self,
+            input,
+            semantics,
+            search_start: Some(0),
+        }
+    }
+
+    /// Replaces every non-overlapping match with `replacement`, expanding group
+    /// references as in [`Regex::replace`].
+    pub fn replace_all(&self, input: &str, replacement: &str) -> String {
+        self.replacen(input, replacement, usize::MAX)
+    }
+
+    /// Replaces the first `n` non-overlapping matches with `replacement`,
+    /// expanding group references as in [`Regex::replace`].
+    pub fn replacen(&self, input: &str, replacement: &str, n: usize) -> String {
+        let mut result = String::with_capacity(input.len());
+        let mut last_end = 0;
+        for captures in self.captures_up_to(input, n) {
+            let (start, end) = captures.span();
+            result.push_str(&input[last_end..start]);
+            captures.expand(replacement, &mut result);
+            last_end = end;
+        }
+        result.push_str(&input[last_end..]);
+        result
+    }
+
+    /// Replaces every non-overlapping match with what `replacement` returns
+    /// for its captures, for replacements that can't be written as a
+    /// template, such as the match in upper case.
+    pub fn replace_with<F>(&self, input: &str, mut replacement: F) -> String
+    where
+        F: FnMut(&Captures<'_>) -> String,
+    {
+        let mut result = String::with_capacity(input.len());
+        let mut last_end = 0;
+        for captures in self.captures_iter(input) {
+            let (start, end) = captures.span();
+            result.push_str(&input[last_end..start]);
+            result.push_str(&replacement(&captures));
+            last_end = end;
+        }
+        result.push_str(&input[last_end..]);
+        result
+    }
+
+    /// Returns the pieces of `input` between matches. A match at the very
+    /// start or end produces an empty first or last piece.
+    pub fn split(&self, input: &str) -> Vec<String> {
+        self.splitn(input, usize::MAX)
+    }
+
+    /// Returns at most `n` pieces of `input` between matches, as in
+    /// [`str::splitn`]. The last piece is the rest of the input after the
+    /// first `n - 1` matches, so splitting `a,b,c,d` on `,` with `n` of 2
+    /// gives `a` and `b,c,d`.
+    pub fn splitn(&self, input: &str, n: usize) -> Vec<String> {
+        if n == 0 {
+            return Vec::new();
+        }
+        let mut pieces = Vec::new();
+        let mut last_end = 0;
+        for found in self.find_iter(input).take(n - 1) {
+            pieces.push(input[last_end..found.start()].to_string());
+            last_end = found.end();
+        }
+        pieces.push(input[last_end..].to_string());
+        pieces
+    }
+}
+
+/// An iterator over the non-overlapping matches of a [`Regex`], as returned by
+/// [`Regex::find_iter`].
+#[derive(Debug, Clone)]
+pub struct Matches<'r, 't> {
+    regex: &'r Regex,
+    input: &'t str,
+    semantics: MatchSemantics,
+    // `None` once the end of the input has been searched
+    search_start: Option<usize>,
+}
+
+impl<'t> Iterator for Matches<'_, 't> {
+    type Item = Match<'t>;
+
+    fn next(&mut self) -> Option<Match<'t>> {
+        let search_start = self.search_start?;
+        let found = self.regex.search(self.input, search_start, self.semantics, false);
+        let Some(((start, end), _)) = found else {
+            self.search_start = None;
+            return None;
+        };
+        self.search_start = next_search_start(self.input, start, end);
+        Some(Match { input: self.input, start, end })
+    }
+}
+
+// Where to look for the match after the one spanning `start..end`. After an
+// empty match, steps over the next char so the same position isn't matched
+// again, or returns `None` if it was at the end of the input.
+fn next_search_start(input: &str, start: usize, end: usize) -> Option<usize> {
+    if end > start {
+        return Some(end);
+    }
+    input[end..].chars().next().map(|c| end + c.len_utf8())
+}
+
+impl<'t> Match<'t> {
+    /// Byte offset of the start of the match.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Byte offset just past the end of the match.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// The matched text.
+    pub fn as_str(&self) -> &'t str {
+        &self.input[self.start..self.end]
+    }
+}
+
+impl<'t> Captures<'t> {
+    /// Returns the text of group `i`, where group 0 is the whole match, or
+    /// `None` if the group did not participate in the match.
+    ///
+    /// A group under a quantifier holds its capture from the last iteration
+    /// that set it, as in PCRE: `(ab)+` on `ababab` captures the final `ab`,
+    /// and in `((a)|b)+` on `ab` group 2 keeps the `a` from the first pass.
+    pub fn get(&self, i: usize) -> Option<&str> {
+        let (start, end) = self.group_span(i)?;
+        Some(&self.input[start..end])
+    }
+
+    /// Byte offsets of the start and end of the whole match.
+    pub fn span(&self) -> (usize, usize) {
+        self.span
+    }
+
+    /// Byte offsets of the start and end of group `i`, where group 0 is the
+    /// whole match, or `None` if the group did not participate in the match.
+    /// The text of the group is the input sliced at these offsets.
+    pub fn group_span(&self, i: usize) -> Option<(usize, usize)> {
+        match i {
+            0 => Some(self.span),
+            _ => *self.groups.get(i - 1)?,
+        }
+    }
+
+    /// Returns the text of the group named `name`, or `None` if there is no
+    /// such group or it did not participate in the match.
+    pub fn name(&self, name: &str) -> Option<&str> {
+        let slot = self
+            .group_names
+            .iter()
+            .position(|group_name| group_name.as_deref() == Some(name))?;
+        self.get(slot + 1)
+    }
+
+    /// Returns the text of each named group that participated in the match,
+    /// keyed by name.
+    pub fn named(&self) -> HashMap<String, String> {
+        self.group_names
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, name)| Some((name.clone()?, self.get(slot + 1)?.to_string())))
+            .collect()
+    }
+
+    /// Appends `replacement` to `dst`, substituting `$N` or `${N}` with the
+    /// text of group N, `$name` or `${name}` with the text of the group with
+    /// that name, and `$$` with a single `$`. Groups that don't exist or
+    /// didn't participate are replaced with nothing. A `$` not followed by
+    /// one of these is kept as is.
+    pub fn expand(&self, replacement: &str, dst: &mut String) {
+        let mut chars = replacement.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '$' {
+                dst.push(c);
+                continue;
+            }
+            match chars.peek() {
+                Some('$') => {
+                    chars.next();
+                    dst.push('$');
+                }
+                Some('{') => {
+                    let rest = chars.clone().skip(1).collect::<String>();
+                    match rest.split_once('}') {
+                        Some((reference, _)) => {
+                            chars.nth(reference.chars().count() + 1);
+                            dst.push_str(self.reference(reference));
+                        }
+                        None => dst.push('$'),
+                    }
+                }
+                Some(next) if next.is_ascii_digit() => {
+                    let group = parse_number(&mut chars).unwrap_or(0);
+                    dst.push_str(self.get(group).unwrap_or(""));
+                }
+                Some(next) if next.is_alphabetic() || *next == '_' => {
+                    let mut name = String::new();
+                    while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                        name.push(c);
+                    }
+                    dst.push_str(self.name(&name).unwrap_or(""));
+                }
+                _ => dst.push('$'),
+            }
+        }
+    }
+
+    // The text of the group a `${...}` reference names, by number or by name
+    fn reference(&self, reference: &str) -> &str {
+        let text = match reference.parse() {
+            Ok(group) => self.get(group),
+            Err(_) => self.name(reference),
+        };
+        text.unwrap_or("")
+    }
+
+    /// Iterates over the whole match followed by each numbered group.
+    pub fn iter(&self) -> impl Iterator<Item = Option<&str>> + '_ {
+        (0..=self.groups.len()).map(|i| self.get(i))
+    }
+}
+
+// Compiles a pattern passed to one of the functions below. With the `cache`
+// feature, each thread keeps the patterns it used most recently compiled.
+#[cfg(not(feature = "cache"))]
+fn compile(pattern: &str) -> Result<Regex, ParseError> {
+    Regex::new(pattern)
+}
+
+#[cfg(feature = "cache")]
+fn compile(pattern: &str) -> Result<Arc<Regex>, ParseError> {
+    use crate::regex_cache::RegexCache;
+    use std::cell::RefCell;
+
+    const CAPACITY: usize = 16;
+    thread_local! {
+        static CACHE: RefCell<RegexCache> = RefCell::new(RegexCache::new(CAPACITY));
+    }
+    CACHE.with(|cache| cache.borrow_mut().get(pattern))
+}
+
+/// Replaces the leftmost match of `pattern` in `input`; see [`Regex::replace`].
+pub fn replace(input: &str, pattern: &str, replacement: &str) -> Result<String, ParseError> {
+    Ok(compile(pattern)?.replace(input, replacement))
+}
+
+/// Replaces every match of `pattern` in `input`; see [`Regex::replace_all`].
+pub fn replace_all(input: &str, pattern: &str, replacement: &str) -> Result<String, ParseError> {
+    Ok(compile(pattern)?.replace_all(input, replacement))
+}
+
+/// Replaces the first `n` matches of `pattern` in `input`; see
+/// [`Regex::replacen`].
+pub fn replacen(
+    input: &str,
+    pattern: &str,
+    replacement: &str,
+    n: usize,
+) -> Result<String, ParseError> {
+    Ok(compile(pattern)?.replacen(input, replacement, n))
+}
+
+/// Replaces every match of `pattern` in `input` with what `replacement`
+/// returns for it, given the whole match followed by the text of each numbered
+/// group as in [`captures`]; see [`Regex::replace_with`].
+pub fn replace_with<F>(input: &str, pattern: &str, mut replacement: F) -> Result<String, ParseError>
+where
+    F: FnMut(&[Option<String>]) -> String,
+{
+    Ok(compile(pattern)?.replace_with(input, |captures| {
+        let groups: Vec<_> = captures.iter().map(|group| group.map(str::to_string)).collect();
+        replacement(&groups)
+    }))
+}
+
+/// Splits `input` on matches of `pattern`; see [`Regex::split`].
+pub fn split(input: &str, pattern: &str) -> Result<Vec<String>, ParseError> {
+    Ok(compile(pattern)?.split(input))
+}
+
+/// Splits `input` on the first `n - 1` matches of `pattern`; see
+/// [`Regex::splitn`].
+pub fn splitn(input: &str, pattern: &str, n: usize) -> Result<Vec<String>, ParseError> {
+    Ok(compile(pattern)?.splitn(input, n))
+}
+
+/// Returns the byte offsets of the whole match followed by those of each
+/// numbered group, with `None` for groups that did not participate in the
+/// match. Unlike [`captures`], nothing is copied out of `input`.
+pub fn captures_spans(
+    input: &str,
+    pattern: &str,
+) -> Result<Option<Vec<Option<Span>>>, ParseError> {
+    let captures = compile(pattern)?.captures(input);
+    Ok(captures.map(|captures| {
+        (0..=captures.groups.len()).map(|i| captures.group_span(i)).collect()
+    }))
+}
+
+/// Returns the whole match followed by the text of each numbered group, with
+/// `None` for groups that did not participate in the match.
+pub fn captures(input: &str, pattern: &str) -> Result<Option<Vec<Option<String>>>, ParseError> {
+    let captures = compile(pattern)?.captures(input);
+    Ok(captures.map(|captures| captures.iter().map(|group| group.map(str::to_string)).collect()))
+}
+
+/// Returns the whole match and the text of each numbered group for every
+/// non-overlapping match, as [`captures`] does for the first.
+pub fn captures_iter(input: &str, pattern: &str) -> Result<Vec<Vec<Option<String>>>, ParseError> {
+    let all = compile(pattern)?.captures_iter(input);
+    Ok(all
+        .iter()
+        .map(|captures| captures.iter().map(|group| group.map(str::to_string)).collect())
+        .collect())
+}
+
+/// Returns the text of each named group that participated in the leftmost
+/// match, keyed by name, or `None` if there is no match.
+pub fn captures_named(input: &str, pattern: &str) -> Result<Option<HashMap<String, String>>, ParseError> {
+    let captures = compile(pattern)?.captures(input);
+    Ok(captures.map(|captures| captures.named()))
+}
+
+pub fn match_pattern(input_line: &str, pattern_str: &str) -> Result<bool, ParseError> {
+    Ok(compile(pattern_str)?.is_match(input_line))
+}
+
+/// Returns whether `pattern` matches at the start of `input`; see
+/// [`Regex::is_match_at_start`].
+pub fn is_match_at_start(input: &str, pattern: &str) -> Result<bool, ParseError> {
+    Ok(compile(pattern)?.is_match_at_start(input))
+}
+
+/// Returns the end offset of the shortest match of `pattern` at the start of
+/// `input`; see [`Regex::shortest_match`].
+pub fn shortest_match(input: &str, pattern: &str) -> Result<Option<usize>, ParseError> {
+    Ok(compile(pattern)?.shortest_match(input))
+}
+
+/// Returns whether `pattern` matches the whole of `input`; see
+/// [`Regex::is_full_match`].
+pub fn is_full_match(input: &str, pattern: &str) -> Result<bool, ParseError> {
+    Ok(compile(pattern)?.is_full_match(input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found<'t>(pattern: &str, input: &'t str) -> Option<&'t str> {
+        Regex::new(pattern).unwrap().find(input).map(|m| m.as_str())
+    }
+
+    fn found_span(pattern: &str, input: &str) -> Option<Span> {
+        Regex::new(pattern).unwrap().find(input).map(|m| (m.start(), m.end()))
+    }
+
+    #[test]
+    fn greedy_and_lazy_quantifiers_both_match() {
+        assert!(match_pattern("aaa", "a+a").unwrap());
+        assert!(match_pattern("aaa", "a+?a").unwrap());
+    }
+
+    #[test]
+    fn lazy_quantifier_takes_the_shortest_match() {
+        assert_eq!(found("a+a", "aaa"), Some("aaa"));
+        assert_eq!(found("a+?a", "aaa"), Some("aa"));
+    }
+
+    #[test]
+    fn captures_returns_each_group() {
+        let groups = captures("bob@corp", r"(\w+)@(\w+)").unwrap().unwrap();
+        let expected = ["bob@corp", "bob", "corp"].map(|group| Some(group.to_string()));
+        assert_eq!(groups, expected);
+        assert_eq!(captures("nobody", r"(\w+)@(\w+)").unwrap(), None);
+    }
+
+    #[test]
+    fn replace_expands_group_references() {
+        assert_eq!(replace("hello world", r"(\w+) (\w+)", "$2 $1").unwrap(), "world hello");
+        assert_eq!(replace("cost: 5", r"\d", "$$").unwrap(), "cost: $");
+        assert_eq!(replace("no digits", r"\d", "#").unwrap(), "no digits");
+    }
+
+    #[test]
+    fn replace_all_replaces_every_match() {
+        assert_eq!(replace_all("a1b2c3", r"\d", "#").unwrap(), "a#b#c#");
+        assert_eq!(replace("a1b2c3", r"\d", "#").unwrap(), "a#b2c3");
+    }
+
+    #[test]
+    fn split_divides_at_each_match() {
+        assert_eq!(split("a,b;c", "[,;]").unwrap(), ["a", "b", "c"]);
+        assert_eq!(split("abc", "[,;]").unwrap(), ["abc"]);
+    }
+
+    #[test]
+    fn length_bounds_of_literals_and_quantifiers() {
+        let bounds = |pattern| Regex::new(pattern).unwrap().length_bounds();
+        assert_eq!(bounds("abc"), (3, Some(3)));
+        assert_eq!(bounds("a+"), (1, None));
+        assert_eq!(bounds("a?b"), (1, Some(2)));
+    }
+
+    #[test]
+    fn backreference_repeats_the_captured_text() {
+        assert_eq!(found(r"(a+)\1", "aaaa"), Some("aaaa"));
+        assert_eq!(found(r"(ab)\1", "abab"), Some("abab"));
+        assert!(!match_pattern("abba", r"(ab)\1").unwrap());
+    }
+
+    #[test]
+    fn groups_capture_multibyte_chars() {
+        let regex = Regex::new("(.)(.)").unwrap();
+        let captures = regex.captures("é🦀").unwrap();
+        assert_eq!(captures.get(1), Some("é"));
+        assert_eq!(captures.get(2), Some("🦀"));
+        assert_eq!(captures.group_span(2), Some((2, 6)));
+        assert_eq!(found("caf(e|é)", "un café"), Some("café"));
+    }
+
+    #[test]
+    fn leading_dot_star_tries_only_line_starts() {
+        let line = "a".repeat(100_000);
+        assert!(!Regex::new(".*[x]").unwrap().is_match(&line));
+        let input = format!("{}\n{}7", line, line);
+        let found = Regex::new(r".*\d").unwrap().find(&input).unwrap();
+        assert_eq!((found.start(), found.end()), (100_001, input.len()));
+        assert_eq!(found_span(".*x", "ab\ncx"), Some((3, 5)));
+    }
+
+    #[test]
+    fn trailing_backslash_is_an_error() {
+        assert_eq!(parse(r"a\"), Err(ParseError::TrailingBackslash));
+        assert_eq!(parse(r"\"), Err(ParseError::TrailingBackslash));
+        assert!(match_pattern(r"a\", r"a\\").unwrap());
+    }
+
+    #[test]
+    fn alternation_inside_a_group() {
+        assert!(match_pattern("abef", "(ab|cd)ef").unwrap());
+        assert!(match_pattern("cdef", "(ab|cd)ef").unwrap());
+        assert!(!match_pattern("abcd", "(ab|cd)ef").unwrap());
+    }
+
+    #[test]
+    fn quantified_groups() {
+        let captures = Regex::new("(ab)+").unwrap().captures("ababab").unwrap();
+        assert_eq!(captures.get(0), Some("ababab"));
+        assert_eq!(captures.group_span(1), Some((4, 6)));
+        let captures = Regex::new("(ab)?c").unwrap().captures("c").unwrap();
+        assert_eq!(captures.get(0), Some("c"));
+        assert_eq!(captures.get(1), None);
+    }
+
+    #[test]
+    fn word_class_covers_underscores_and_digits() {
+        assert_eq!(found(r"\w+", "snake_case_123"), Some("snake_case_123"));
+        assert_eq!(found(r"\w+", "-- snake_case_123 --"), Some("snake_case_123"));
+    }
+
+    #[test]
+    fn group_captures_digits_after_letters() {
+        let captures = Regex::new(r"(\d+)").unwrap().captures("abc42").unwrap();
+        assert_eq!(captures.get(1), Some("42"));
+        assert_eq!(captures.group_span(1), Some((3, 5)));
+    }
+
+    #[test]
+    fn backreferences_past_nine() {
+        let pattern = r"(a)(b)(c)(d)(e)(f)(g)(h)(i)(j)(k)-\10\11";
+        assert!(match_pattern("abcdefghijk-jk", pattern).unwrap());
+        assert!(!match_pattern("abcdefghijk-a0a1", pattern).unwrap());
+        // With only one group `\10` names a missing group, not `\1` then `0`
+        assert!(!match_pattern("aa0", r"(a)\10").unwrap());
+    }
+
+    #[test]
+    fn empty_alternative_matches_nothing() {
+        for pattern in ["(a|)b", "(|a)b"] {
+            assert_eq!(found(pattern, "ab"), Some("ab"), "{}", pattern);
+            assert_eq!(found(pattern, "b"), Some("b"), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn alternation_backtracks_into_later_branches() {
+        assert_eq!(found("(a|ab)c", "abc"), Some("abc"));
+        assert_eq!(found("(ab|a)", "a"), Some("a"));
+        assert_eq!(found("(ab|a)", "ab"), Some("ab"));
+    }
+
+    #[test]
+    fn end_anchor_after_quantifiers() {
+        assert_eq!(found(".*$", "abc123"), Some("abc123"));
+        assert_eq!(found(r"\d+$", "abc123"), Some("123"));
+        assert!(!match_pattern("abc123x", r"\d+$").unwrap());
+    }
+
+    #[test]
+    fn lookahead_checks_without_consuming() {
+        assert_eq!(found("foo(?=bar)", "foobar"), Some("foo"));
+        assert!(!match_pattern("foobaz", "foo(?=bar)").unwrap());
+        assert_eq!(found("foo(?!bar)", "foobaz"), Some("foo"));
+        assert!(!match_pattern("foobar", "foo(?!bar)").unwrap());
+    }
+
+    #[test]
+    fn lookbehind_checks_the_text_before() {
+        assert_eq!(found(r"(?<=@)\w+", "@user"), Some("user"));
+        assert_eq!(found(r"(?<!@)\b\w+", "@user name"), Some("name"));
+        assert_eq!(Regex::new("(?<=a+)b").err(), Some(ParseError::VariableLookbehind));
+    }
+
+    #[test]
+    fn named_groups() {
+        let pattern = r"(?<year>\d{4})-(?<month>\d{2})";
+        let named = captures_named("on 2024-05", pattern).unwrap().unwrap();
+        assert_eq!(named["year"], "2024");
+        assert_eq!(named["month"], "05");
+        let captures = Regex::new(pattern).unwrap().captures("2024-05").unwrap();
+        assert_eq!(captures.name("month"), Some("05"));
+        assert_eq!(replace("2024-05", pattern, "${month}/${year}").unwrap(), "05/2024");
+    }
+
+    #[test]
+    fn repeat_without_a_minimum_starts_at_zero() {
+        assert_eq!(found("ba{,3}", "baaaa"), Some("baaa"));
+        assert_eq!(found("ba{,3}", "b"), Some("b"));
+        assert_eq!(Regex::new("a{,3}").unwrap().length_bounds(), (0, Some(3)));
+    }
+
+    #[test]
+    fn optional_char_gives_way_to_what_follows() {
+        assert_eq!(found("a?a", "a"), Some("a"));
+        assert_eq!(found("a?a", "aa"), Some("aa"));
+        assert!(match_pattern("color", "colou?r").unwrap());
+        assert!(match_pattern("colour", "colou?r").unwrap());
+        assert!(!match_pattern("colouur", "colou?r").unwrap());
+    }
+
+    #[test]
+    fn possessive_quantifier_never_gives_back() {
+        assert!(!match_pattern("aaa", "a++a").unwrap());
+        assert!(match_pattern("aaa", "a+a").unwrap());
+        assert_eq!(found("a++b", "aaab"), Some("aaab"));
+    }
+
+    #[test]
+    fn escaped_chars_merge_into_the_literal_around_them() {
+        assert_eq!(parse(r"a\.b").unwrap(), [Pattern::Literal("a.b".to_string())]);
+        assert!(!match_pattern("axb", r"a\.b").unwrap());
+    }
+
+    #[test]
+    fn both_anchors_match_the_whole_input() {
+        assert!(match_pattern("abc", "^abc$").unwrap());
+        assert!(!match_pattern("abcd", "^abc$").unwrap());
+        assert!(!match_pattern("xabc", "^abc$").unwrap());
+        assert!(match_pattern("aaa", "^a+$").unwrap());
+        assert!(!match_pattern("aab", "^a+$").unwrap());
+        assert!(!match_pattern("", "^a+$").unwrap());
+    }
+
+    #[test]
+    fn negated_class_consumes_one_char() {
+        assert!(match_pattern("b", "[^a]").unwrap());
+        assert!(match_pattern("é", "[^a]").unwrap());
+        assert!(!match_pattern("a", "[^a]").unwrap());
+        assert!(!match_pattern("", "[^a]").unwrap());
+        assert!(!match_pattern("ba", "b[^a]").unwrap());
+    }
+
+    #[test]
+    fn anchors_inside_groups_and_alternations() {
+        assert_eq!(found_span("(^)a", "aa"), Some((0, 1)));
+        assert!(!match_pattern("ba", "(^)a").unwrap());
+        assert_eq!(found_span("a($)", "aa"), Some((1, 2)));
+        assert!(!match_pattern("ab", "a($)").unwrap());
+        assert_eq!(found_span("(^a|b$)", "cab"), Some((2, 3)));
+        assert_eq!(found_span("(^a|b$)", "abc"), Some((0, 1)));
+    }
+
+    #[test]
+    fn empty_input_matches_patterns_that_can_be_empty() {
+        for pattern in ["^$", "^", "$", "a?"] {
+            assert_eq!(found_span(pattern, ""), Some((0, 0)), "{}", pattern);
+        }
+        assert!(!match_pattern("", "a").unwrap());
+    }
+
+    #[test]
+    fn inline_case_insensitive_flag() {
+        assert!(match_pattern("CAT", "(?i)cat").unwrap());
+        assert!(match_pattern("CATbar", "(?i:cat)bar").unwrap());
+        assert!(!match_pattern("CATBAR", "(?i:cat)bar").unwrap());
+    }
+
+    #[test]
+    fn case_insensitive_backreference() {
+        assert!(match_pattern("aA", r"(?i)(a)\1").unwrap());
+        assert!(!match_pattern("aA", r"(a)\1").unwrap());
+        assert_eq!(found(r"(?i)(ab)\1", "xAbaB"), Some("AbaB"));
+        let options = RegexOptions { case_insensitive: true, ..RegexOptions::default() };
+        let regex = Regex::with_options(r"(é)\1", options).unwrap();
+        assert!(regex.is_match("éÉ"));
+        assert_eq!(Pattern::BackReference(1, true).to_string(), r"(?i:\1)");
+    }
+
+    #[test]
+    fn stacked_quantifiers() {
+        assert!(Regex::new("a+?").is_ok());
+        assert!(Regex::new("a++").is_ok());
+        assert_eq!(Regex::new("a**").err(), Some(ParseError::InvalidQuantifier));
+        assert_eq!(Regex::new("a+?+").err(), Some(ParseError::InvalidQuantifier));
+    }
+
+    #[test]
+    fn pipe_in_a_bracket_expression_is_a_member() {
+        for input in ["a", "|", "b"] {
+            assert_eq!(found("[a|b]", input), Some(input));
+        }
+        assert!(!match_pattern("c", "[a|b]").unwrap());
+    }
+
+    #[test]
+    fn leftmost_longest_prefers_the_longer_branch() {
+        let regex = Regex::new("(a|ab)").unwrap();
+        assert_eq!(regex.find("ab").unwrap().as_str(), "a");
+        let longest = regex.find_with("ab", MatchSemantics::LeftmostLongest).unwrap();
+        assert_eq!(longest.as_str(), "ab");
+        let all: Vec<_> = regex
+            .find_iter_with("ab xab", MatchSemantics::LeftmostLongest)
+            .map(|m| m.as_str())
+            .collect();
+        assert_eq!(all, ["ab", "ab"]);
+    }
+
+    // The pattern as parsed, without the shared prefixes of its alternations
+    // factored out
+    fn unfactored(pattern: &str) -> Regex {
+        let regex = Regex::new(pattern).unwrap();
+        Regex { patterns: parse(pattern).unwrap(), ..regex }
+    }
+
+    #[test]
+    fn factoring_prefixes_does_not_change_matches() {
+        let patterns =
+            ["cat|car|can", "(cat|car|can)s?", "x(ab|abc|a)y", "(ab|abc)+d", "(?:foo|foobar|fo)"];
+        let inputs = ["cat", "cars", "ca", "xaby", "xabcy", "xay", "ababcd", "foobar", "fo"];
+        for pattern in patterns {
+            let factored = Regex::new(pattern).unwrap();
+            let unfactored = unfactored(pattern);
+            assert_ne!(factored.patterns, unfactored.patterns, "{}", pattern);
+            for input in inputs {
+                let groups = |regex: &Regex| -> Vec<(Span, Vec<Option<String>>)> {
+                    let captures = regex.captures_iter(input);
+                    let groups = |c: &Captures| c.iter().map(|g| g.map(str::to_string)).collect();
+                    captures.iter().map(|c| (c.span(), groups(c))).collect()
+                };
+                assert_eq!(groups(&factored), groups(&unfactored), "{} on {}", pattern, input);
+                let longest = |regex: &Regex| -> Vec<Span> {
+                    regex
+                        .find_iter_with(input, MatchSemantics::LeftmostLongest)
+                        .map(|m| (m.start(), m.end()))
+                        .collect()
+                };
+                assert_eq!(longest(&factored), longest(&unfactored), "{} on {}", pattern, input);
+            }
+        }
+    }
+
+    #[test]
+    fn empty_matches_advance_past_each_char() {
+        let regex = Regex::new("a*").unwrap();
+        let spans: Vec<_> = regex.find_all("bbb").iter().map(|m| (m.start(), m.end())).collect();
+        assert_eq!(spans, [(0, 0), (1, 1), (2, 2), (3, 3)]);
+        let spans: Vec<_> = regex.find_iter("baab").map(|m| (m.start(), m.end())).collect();
+        assert_eq!(spans, [(0, 0), (1, 3), (3, 3), (4, 4)]);
+    }
+
+    #[test]
+    fn backreference_to_a_group_in_the_taken_branch() {
+        let regex = Regex::new(r"(x(a)|y(b))\2").unwrap();
+        let captures = regex.captures("xaa").unwrap();
+        assert_eq!(captures.get(2), Some("a"));
+        assert_eq!(captures.get(3), None);
+        // Group 2 never captures when the `y` branch is taken
+        assert!(!regex.is_match("ybb"));
+        let captures = Regex::new("(?:x(a)b|x(a)c)").unwrap().captures("xac").unwrap();
+        assert_eq!(captures.get(1), None);
+        assert_eq!(captures.get(2), Some("a"));
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn trace_records_each_attempt_in_order() {
+        let (matched, events) = Regex::new("a(b|c)").unwrap().trace("ac");
+        assert!(matched);
+        let events: Vec<_> = events
+            .iter()
+            .map(|event| (event.pattern.as_str(), event.offset, event.matched))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                ("a", 0, true),
+                ("(b|c)", 1, true),
+                ("b|c", 1, true),
+                ("(?:b)", 1, false),
+                ("b", 1, false),
+                ("(?:c)", 1, true),
+                ("c", 1, true),
+            ]
+        );
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn only_trace_records_attempts() {
+        let fresh = Regex::new("a(b|c)").unwrap().trace("ac").1;
+        assert!(!fresh.is_empty());
+
+        // Matching first leaves nothing behind for the trace to pick up, and
+        // each trace starts from scratch
+        let regex = Regex::new("a(b|c)").unwrap();
+        assert!(regex.is_match("ac"));
+        assert_eq!(regex.trace("ac"), (true, fresh.clone()));
+        assert!(regex.is_match("ac"));
+        assert_eq!(regex.trace("ac"), (true, fresh));
+    }
+
+    #[test]
+    fn captures_without_groups_has_only_the_whole_match() {
+        let captures = Regex::new("abc").unwrap().captures("xabcx").unwrap();
+        assert_eq!(captures.span(), (1, 4));
+        assert_eq!(captures.iter().collect::<Vec<_>>(), [Some("abc")]);
+        assert_eq!(captures.get(1), None);
+    }
+
+    #[test]
+    fn backreference_to_nested_and_unused_groups() {
+        assert!(match_pattern("catcat", r"((cat)|(dog))\2").unwrap());
+        // Group 2 never takes part when the `dog` branch matches
+        assert!(!match_pattern("dogdog", r"((cat)|(dog))\2").unwrap());
+        assert_eq!(found(r"(x(y)z)\2", "xyzy"), Some("xyzy"));
+    }
+
+    #[test]
+    fn match_at_start_only() {
+        assert!(is_match_at_start("abcd", "abc").unwrap());
+        assert!(!is_match_at_start("xabc", "abc").unwrap());
+        assert!(Regex::new("abc").unwrap().is_match_at_start("abcd"));
+    }
+
+    #[test]
+    fn full_match_spans_the_whole_input() {
+        assert!(is_full_match("aaa", "a+").unwrap());
+        assert!(!is_full_match("aaab", "a+").unwrap());
+        // The shorter branch matches first, but only the longer one is full
+        assert!(is_full_match("ab", "a|ab").unwrap());
+    }
+
+    #[test]
+    fn ascii_option_limits_word_chars() {
+        assert!(Regex::new(r"\w").unwrap().is_match("é"));
+        let options = RegexOptions { ascii: true, ..RegexOptions::default() };
+        let ascii = Regex::with_options(r"\w", options).unwrap();
+        assert!(!ascii.is_match("é"));
+        assert!(ascii.is_match("e_1"));
+    }
+
+    #[test]
+    fn posix_classes_in_brackets() {
+        assert_eq!(found("[[:digit:]]+", "ab123c"), Some("123"));
+        assert_eq!(found("[[:alpha:]]+", "12abC3"), Some("abC"));
+        assert_eq!(found("a[[:space:]]b", "a\tb"), Some("a\tb"));
+        assert_eq!(found("[^[:digit:]]+", "12ab3"), Some("ab"));
+        assert_eq!(
+            Regex::new("[[:foo:]]").err(),
+            Some(ParseError::InvalidClassName("foo".to_string()))
+        );
+    }
+
+    #[test]
+    fn escaped_metacharacters_are_literals() {
+        assert!(match_pattern("a.b", r"a\.b").unwrap());
+        assert!(!match_pattern("axb", r"a\.b").unwrap());
+        assert!(match_pattern("a.b", r"^a\.b$").unwrap());
+        assert!(!match_pattern("xa.b", r"^a\.b$").unwrap());
+        assert!(!match_pattern("a.bx", r"^a\.b$").unwrap());
+        for (pattern, input) in [
+            (r"a\+", "a+"),
+            (r"a\*", "a*"),
+            (r"a\?", "a?"),
+            (r"\(a\)", "(a)"),
+            (r"\[a\]", "[a]"),
+            (r"a\|b", "a|b"),
+            (r"\^a\$", "^a$"),
+        ] {
+            assert_eq!(found(pattern, input), Some(input), "{}", pattern);
+        }
+        assert!(!match_pattern("aa", r"a\+").unwrap());
+        assert!(!match_pattern("a", r"a\|b").unwrap());
+    }
+
+    #[test]
+    fn step_limit_abandons_catastrophic_backtracking() {
+        let options = RegexOptions { step_limit: Some(10_000), ..RegexOptions::default() };
+        let regex = Regex::with_options("(a+)+b", options).unwrap();
+        // The `b` is present, so the required literal check doesn't rule the
+        // input out, but it can't be reached from the run of `a`s
+        let input = "a".repeat(30) + "c b";
+        assert_eq!(regex.try_is_match(&input), Err(MatchError::ComplexityLimitExceeded));
+        assert!(!regex.is_match(&input));
+        assert_eq!(regex.try_is_match("aab"), Ok(true));
+    }
+
+    #[test]
+    fn line_over_100_000_chars_matches_promptly() {
+        let line = "ab".repeat(60_000);
+        let regex = Regex::new(r"b\d+").unwrap();
+        assert!(!regex.is_match(&line));
+        let with_digits = format!("{}b42{}", line, line);
+        let found = regex.find(&with_digits).unwrap();
+        assert_eq!((found.start(), found.as_str()), (120_000, "b42"));
+        assert!(Regex::new(r"^\w+$").unwrap().is_match(&line));
+    }
+
+    #[test]
+    fn captures_iter_collects_every_match() {
+        let regex = Regex::new(r"(\w+)=(\w+)").unwrap();
+        let captures = regex.captures_iter("a=1 b=2");
+        let pairs: Vec<_> =
+            captures.iter().map(|c| (c.get(1).unwrap(), c.get(2).unwrap())).collect();
+        assert_eq!(pairs, [("a", "1"), ("b", "2")]);
+    }
+
+    #[test]
+    fn whitespace_escapes() {
+        assert_eq!(parse(r"a\tb").unwrap(), [Pattern::Literal("a\tb".to_string())]);
+        assert!(match_pattern("a\tb", r"a\tb").unwrap());
+        assert!(!match_pattern("a b", r"a\tb").unwrap());
+        assert!(match_pattern("a\r\n", r"a\r\n").unwrap());
+    }
+
+    #[test]
+    fn hex_escapes() {
+        assert_eq!(parse(r"\x41\x42").unwrap(), [Pattern::Literal("AB".to_string())]);
+        assert!(match_pattern("AB", r"\x41\x42").unwrap());
+        assert!(!match_pattern("ab", r"\x41\x42").unwrap());
+        assert_eq!(parse(r"\x4"), Err(ParseError::InvalidHexEscape));
+        assert_eq!(parse(r"\x4g"), Err(ParseError::InvalidHexEscape));
+    }
+
+    #[test]
+    fn unicode_escapes() {
+        assert_eq!(parse(r"\u{e9}").unwrap(), [Pattern::Literal("é".to_string())]);
+        assert!(match_pattern("café", r"caf\u{e9}").unwrap());
+        assert!(match_pattern("😀", r"\u{1F600}").unwrap());
+        assert_eq!(parse(r"\u{110000}"), Err(ParseError::InvalidUnicodeEscape));
+        assert_eq!(parse(r"\u{e9"), Err(ParseError::InvalidUnicodeEscape));
+    }
+
+    #[test]
+    fn end_anchor_before_a_windows_line_ending() {
+        assert_eq!(found_span("foo$", "foo\r"), Some((0, 3)));
+        assert!(!match_pattern("foo\rx", "foo$").unwrap());
+        let options = RegexOptions { multiline: true, ..RegexOptions::default() };
+        let regex = Regex::with_options("o$", options).unwrap();
+        let spans: Vec<_> = regex.find_iter("foo\r\nbar\r\n").map(|m| (m.start(), m.end())).collect();
+        assert_eq!(spans, [(2, 3)]);
+    }
+
+    #[test]
+    fn is_match_and_captures_agree_without_groups() {
+        for (pattern, input) in [(r"\d+", "ab 123 c"), ("c[aeiou]t", "a cot"), ("x+y", "xxz")] {
+            let regex = Regex::new(pattern).unwrap();
+            let found = regex.find(input).map(|m| m.as_str());
+            let captured = regex.captures(input);
+            assert_eq!(found, captured.as_ref().and_then(|c| c.get(0)), "{}", pattern);
+            assert_eq!(regex.is_match(input), found.is_some(), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn dot_matches_one_char_but_not_a_newline() {
+        assert_eq!(found("a.c", "aéc"), Some("aéc"));
+        assert_eq!(found(".", "🦀"), Some("🦀"));
+        assert!(!match_pattern("\n", ".").unwrap());
+        assert!(!match_pattern("a\nc", "a.c").unwrap());
+        assert!(match_pattern("a\nc", "(?s)a.c").unwrap());
+        // At the end of the input there is no char left for `.` to match
+        assert!(!match_pattern("ab", "ab.").unwrap());
+        assert_eq!(found(".{2,3}", "abcd"), Some("abc"));
+        assert_eq!(found("(.|x)y", "xy"), Some("xy"));
+    }
+
+    #[test]
+    fn match_bytes_around_invalid_utf8() {
+        let regex = Regex::new(r"\d+").unwrap();
+        assert_eq!(regex.is_match_bytes(b"abc 42"), Ok(true));
+        assert_eq!(regex.is_match_bytes(b"abc"), Ok(false));
+        assert_eq!(regex.is_match_bytes(b"\xff\xfe42\xc3"), Ok(true));
+        assert_eq!(regex.is_match_bytes(b"\xff\xfe\xc3"), Ok(false));
+        let non_ascii = Regex::new("é").unwrap();
+        assert_eq!(non_ascii.is_match_bytes(b"\xe9"), Err(MatchError::NonAsciiPattern));
+    }
+
+    #[test]
+    fn shortest_match_stops_at_the_first_end() {
+        assert_eq!(shortest_match("aaa", "a+").unwrap(), Some(1));
+        assert_eq!(shortest_match("baaa", "a+").unwrap(), None);
+        assert_eq!(Regex::new("ab*c").unwrap().shortest_match("abbc"), Some(4));
+    }
+
+    #[test]
+    fn required_literal_prefilter() {
+        let regex = Regex::new(r"\d+foo").unwrap();
+        assert_eq!(regex.required.as_deref(), Some("foo"));
+        assert!(!regex.is_match("123 bar"));
+        // Present, but not where the rest of the pattern can reach it
+        assert!(!regex.is_match("foo 123"));
+        assert!(regex.is_match("x123foo"));
+        // Rejected before any matching, so it can't hit the step limit
+        let options = RegexOptions { step_limit: Some(100), ..RegexOptions::default() };
+        let regex = Regex::with_options("(a+)+b", options).unwrap();
+        assert_eq!(regex.try_is_match(&"a".repeat(30)), Ok(false));
+    }
+
+    #[test]
+    fn replacen_replaces_at_most_n_matches() {
+        assert_eq!(replacen("aaa", "a", "b", 2).unwrap(), "bba");
+        assert_eq!(replacen("aaa", "a", "b", 0).unwrap(), "aaa");
+        assert_eq!(replacen("a1b2c3", r"\d", "#", 2).unwrap(), "a#b#c3");
+    }
+
+    #[test]
+    fn validate_reports_the_parse_error() {
+        assert_eq!(validate(r"(a|b)+\d{2,3}"), Ok(()));
+        assert_eq!(validate("(a"), Err(ParseError::UnmatchedParen));
+        assert_eq!(validate("a{2,1}"), Err(ParseError::InvalidRepeatBounds(2, 1)));
+        assert_eq!(validate("[unterminated"), Err(ParseError::UnterminatedClass));
+    }
+
+    #[test]
+    fn escaped_literal_matches_only_itself() {
+        let escaped = Regex::escape("a.b*c(d)");
+        assert_eq!(escaped, r"a\.b\*c\(d\)");
+        let regex = Regex::new(&escaped).unwrap();
+        assert!(regex.is_match("xa.b*c(d)y"));
+        assert!(!regex.is_match("axbbbcd"));
+        assert!(!regex.is_match("a.bc(d)"));
+    }
+
+    #[test]
+    fn inline_multiline_and_dotall_flags() {
+        assert!(Regex::new("(?s)a.b").unwrap().is_match("a\nb"));
+        assert!(!Regex::new("a.b").unwrap().is_match("a\nb"));
+        assert!(Regex::new("(?m)^b").unwrap().is_match("a\nb"));
+        assert!(!Regex::new("^b").unwrap().is_match("a\nb"));
+        assert!(Regex::new("(?m)a$").unwrap().is_match("a\r\nb"));
+        assert!(!Regex::new("(?m)^a$").unwrap().is_match("ab\nb"));
+        assert_eq!(found_span("(?m)^b$", "a\nb\nc"), Some((2, 3)));
+    }
+
+    #[test]
+    fn input_anchors() {
+        assert!(match_pattern("ab", r"\Aab").unwrap());
+        assert!(!match_pattern("xab", r"\Aab").unwrap());
+        assert!(match_pattern("xab", r"ab\z").unwrap());
+        assert!(!match_pattern("abx", r"ab\z").unwrap());
+
+        // Unlike `^` and `$`, they ignore the `multiline` option
+        let options = RegexOptions { multiline: true, ..RegexOptions::default() };
+        assert!(!Regex::with_options(r"\Ab", options).unwrap().is_match("a\nb"));
+        assert!(!Regex::with_options(r"a\z", options).unwrap().is_match("a\nb"));
+        assert!(Regex::with_options("^b", options).unwrap().is_match("a\nb"));
+    }
+
+    #[test]
+    fn parsed_patterns_compare_equal() {
+        assert_eq!(parse("a|b"), parse("a|b"));
+        assert_ne!(parse("a|b"), parse("a|c"));
+        assert_eq!(parse(r"(\d+)\1"), parse(r"(\d+)\1"));
+    }
+
+    #[test]
+    fn quantified_classes() {
+        assert_eq!(found_span("[abc]+", "xxcabz"), Some((2, 5)));
+        assert_eq!(found_span("x[0-9]?y", "ax7yb"), Some((1, 4)));
+        assert_eq!(found_span("x[0-9]?y", "axyb"), Some((1, 3)));
+        assert_eq!(found_span("[^x]+", "xxabx"), Some((2, 4)));
+        assert_eq!(found_span("[^x]+", "xxx"), None);
+    }
+
+    #[test]
+    fn alternation_spans() {
+        assert_eq!(found_span("ab|cd", "xcd"), Some((1, 3)));
+        assert_eq!(found_span("ab|cd", "abcd"), Some((0, 2)));
+        assert_eq!(found_span("ab|cd", "acbd"), None);
+    }
+
+    #[test]
+    fn empty_pattern_matches_at_the_start() {
+        assert_eq!(found_span("", ""), Some((0, 0)));
+        assert_eq!(found_span("", "abc"), Some((0, 0)));
+        assert!(Regex::new("").unwrap().is_match("abc"));
+    }
+
+    #[test]
+    fn find_iter_stops_when_taken_from() {
+        let regex = Regex::new(r"\d").unwrap();
+        let taken: Vec<&str> = regex.find_iter("1a2b3c4").take(2).map(|m| m.as_str()).collect();
+        assert_eq!(taken, ["1", "2"]);
+    }
+
+    #[test]
+    fn displayed_patterns_parse_back_the_same() {
+        for pattern in ["a|b", "x(a|b)y", "(?s).", "[-a]", "[^-a]", "[a-]", "[]a-]", r"(a)(?i)\1"] {
+            let parsed = parse(pattern).unwrap();
+            let displayed: String = parsed.iter().map(|p| p.to_string()).collect();
+            assert_eq!(parse(&displayed).unwrap(), parsed, "{} displayed as {}", pattern, displayed);
+        }
+        assert_eq!(parse("[-a]").unwrap()[0].to_string(), "[-a]");
+    }
+
+    #[test]
+    fn quoted_span_is_literal() {
+        assert!(match_pattern("a.b", r"\Qa.b\E").unwrap());
+        assert!(!match_pattern("axb", r"\Qa.b\E").unwrap());
+        assert!(match_pattern("xa.b+c", r"x\Qa.b+c\E").unwrap());
+        assert_eq!(found_span(r"\Qa.\Eb+", "a.bbb"), Some((0, 5)));
+        // An unterminated span runs to the end of the pattern
+        assert!(match_pattern("a(b", r"\Qa(b").unwrap());
+        assert!(!match_pattern("ab", r"\Qa(b").unwrap());
+    }
+
+    #[test]
+    fn splitn_leaves_the_rest_unsplit() {
+        assert_eq!(splitn("a,b,c", ",", 2).unwrap(), ["a", "b,c"]);
+        assert_eq!(splitn("a,b,c", ",", 1).unwrap(), ["a,b,c"]);
+        assert_eq!(splitn("a,b,c", ",", 5).unwrap(), ["a", "b", "c"]);
+        assert!(splitn("a,b,c", ",", 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn grapheme_option_matches_whole_clusters() {
+        let options = RegexOptions { grapheme: true, ..RegexOptions::default() };
+        let dot = Regex::with_options(".", options).unwrap();
+        assert_eq!(dot.find("e\u{301}").map(|m| m.as_str()), Some("e\u{301}"));
+        assert!(Regex::with_options("^.$", options).unwrap().is_match("e\u{301}"));
+        assert!(!Regex::new("^.$").unwrap().is_match("e\u{301}"));
+    }
+
+    #[test]
+    fn find_from_starts_the_search_at_an_offset() {
+        let regex = Regex::new(r"\d+").unwrap();
+        let m = regex.find_from("12 34", 2).unwrap();
+        assert_eq!((m.start(), m.end()), (3, 5));
+        assert!(regex.find_from("12 34", 5).is_none());
+        // The text before the offset still counts for anchors and boundaries
+        assert!(Regex::new("^a").unwrap().find_from("aaa", 1).is_none());
+        assert!(Regex::new(r"\bb").unwrap().find_from("ab b", 1).is_some_and(|m| m.start() == 3));
+    }
+
+    #[test]
+    fn replace_with_computes_each_replacement() {
+        let regex = Regex::new(r"\w+").unwrap();
+        let upper = regex.replace_with("ab cd", |caps| caps.get(0).unwrap().to_uppercase());
+        assert_eq!(upper, "AB CD");
+        let swapped = replace_with("a=1 b=2", r"(\w)=(\w)", |groups| {
+            format!("{}={}", groups[2].as_deref().unwrap(), groups[1].as_deref().unwrap())
+        });
+        assert_eq!(swapped.unwrap(), "1=a 2=b");
+    }
+
+    #[test]
+    fn group_spans_of_participating_groups() {
+        let captures = Regex::new("x(a)|(b)").unwrap().captures("zxa").unwrap();
+        assert_eq!(captures.group_span(0), Some((1, 3)));
+        assert_eq!(captures.group_span(1), Some((2, 3)));
+        assert_eq!(captures.group_span(2), None);
+        assert_eq!(
+            captures_spans("zxa", "x(a)|(b)").unwrap(),
+            Some(vec![Some((1, 3)), Some((2, 3)), None])
+        );
+        assert_eq!(captures_spans("zzz", "x(a)|(b)").unwrap(), None);
+    }
+
+    #[test]
+    fn bracket_and_caret_as_members() {
+        assert_eq!(found("[]a]", "]"), Some("]"));
+        assert_eq!(found("[]a]", "a"), Some("a"));
+        assert_eq!(found("[]a]+", "x]a]"), Some("]a]"));
+        assert_eq!(found("[a^]", "^"), Some("^"));
+        assert!(!match_pattern("b", "[a^]").unwrap());
+        assert_eq!(found("[^]a]", "]ab"), Some("b"));
+    }
+
+    #[test]
+    fn bytes_and_str_matching_agree() {
+        let cases = [
+            ("abc", "xxabcxx"),
+            ("abc", "xxabxx"),
+            ("abc", ""),
+            ("", "abc"),
+            ("needle", "haystack with a needle in it"),
+            ("a.c", "abc"),
+            (r"\d+", "no digits"),
+            ("^ab", "cab"),
+            ("ab$", "cab"),
+        ];
+        for (pattern, input) in cases {
+            let regex = Regex::new(pattern).unwrap();
+            assert_eq!(
+                regex.is_match_bytes(input.as_bytes()),
+                Ok(regex.is_match(input)),
+                "{} on {:?}",
+                pattern,
+                input
+            );
+        }
+    }
+
+    // Gives at most one byte per read, so every match straddles reads
+    struct ByteAtATime<'a>(&'a [u8]);
+
+    impl Read for ByteAtATime<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some((&first, rest)) = self.0.split_first() else { return Ok(0) };
+            buf[0] = first;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn reader_match_across_reads() {
+        let regex = Regex::new("needle").unwrap();
+        assert!(regex.is_match_reader(ByteAtATime(b"hay needle hay")).unwrap());
+        assert!(!regex.is_match_reader(ByteAtATime(b"hay needl hay")).unwrap());
+        assert!(regex.is_match_reader(ByteAtATime(b"\xffneedle\xfe")).unwrap());
+        let digits = Regex::new(r"\d{3}").unwrap();
+        assert!(digits.is_match_reader(ByteAtATime(b"ab 123")).unwrap());
+        assert!(!digits.is_match_reader(ByteAtATime(b"ab 12")).unwrap());
+    }
 }
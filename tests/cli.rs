@@ -0,0 +1,310 @@
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output, Stdio};
+
+// Runs the program in `dir` with `args`, feeding `stdin` to it
+fn grep_in(dir: &Path, args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_codecrafters-grep"))
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // The program may exit, as on a usage error, before reading its input
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+fn grep(args: &[&str], stdin: &str) -> Output {
+    grep_in(&env::temp_dir(), args, stdin)
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+// Creates an empty directory for one test's files, named after the test
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("codecrafters-grep-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(feature = "encoding")]
+#[test]
+fn encoding_decodes_latin1_files() {
+    let dir = temp_dir("latin1");
+    fs::write(dir.join("menu.txt"), b"caf\xe9\n").unwrap();
+    let output = grep_in(&dir, &["--encoding=latin1", "café", "menu.txt"], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "café\n");
+}
+
+#[cfg(not(feature = "encoding"))]
+#[test]
+fn encoding_other_than_utf8_needs_the_feature() {
+    let dir = temp_dir("latin1");
+    fs::write(dir.join("menu.txt"), b"caf\xe9\n").unwrap();
+    let output = grep_in(&dir, &["-r", "--encoding=latin1", "café", "."], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stdout(&output), "");
+}
+
+#[test]
+fn unknown_encoding_is_an_error_even_when_recursing() {
+    let dir = temp_dir("unknown-encoding");
+    fs::write(dir.join("notes.txt"), "plain\n").unwrap();
+    let output = grep_in(&dir, &["-r", "--encoding=no-such-label", "plain", "."], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stdout(&output), "");
+}
+
+#[test]
+fn pattern_file_matches_any_of_its_patterns() {
+    let dir = temp_dir("pattern-file");
+    fs::write(dir.join("patterns"), "apple\n\\d+\n").unwrap();
+    let output = grep_in(
+        &dir,
+        &["-E", "-f", "patterns"],
+        "apple pie\nbanana\n42 pears\n",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "apple pie\n42 pears\n");
+}
+
+#[test]
+fn recursive_search_prefixes_lines_with_their_path() {
+    let dir = temp_dir("recursive");
+    fs::create_dir_all(dir.join("src/nested")).unwrap();
+    fs::write(dir.join("top.txt"), "needle at the top\nhay\n").unwrap();
+    fs::write(dir.join("src/nested/deep.txt"), "hay\nneedle deep down\n").unwrap();
+    let output = grep_in(&dir, &["-r", "needle", "."], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "src/nested/deep.txt:needle deep down\ntop.txt:needle at the top\n"
+    );
+}
+
+#[test]
+fn each_e_flag_adds_a_pattern() {
+    let output = grep(&["-e", "cat", "-e", "dog"], "cat\nbird\ndog\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "cat\ndog\n");
+}
+
+#[test]
+fn exit_status_tells_match_from_no_match_and_errors() {
+    assert_eq!(grep(&["-E", "a+"], "caat\n").status.code(), Some(0));
+    assert_eq!(grep(&["-E", "b+"], "caat\n").status.code(), Some(1));
+    let output = grep(&["-E", r"a\"], "a\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(grep(&["-E", "(a"], "a\n").status.code(), Some(2));
+}
+
+#[test]
+fn only_matching_prints_each_match_on_its_own_line() {
+    let output = grep(&["-E", "-o", r"\d+"], "a12b345\nnone\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "12\n345\n");
+}
+
+#[test]
+fn word_regexp_matches_whole_words_only() {
+    let output = grep(&["-w", "cat"], "a cat\ncategory\nbobcat\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "a cat\n");
+    assert_eq!(grep(&["-w", "cat"], "category\n").status.code(), Some(1));
+}
+
+#[test]
+fn line_regexp_matches_whole_lines_only() {
+    let output = grep(&["-x", "abc"], "abc\nabcd\nxabc\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "abc\n");
+    assert_eq!(grep(&["-x", "abc"], "abcd\nxabc\n").status.code(), Some(1));
+}
+
+#[test]
+fn fixed_strings_take_dots_literally() {
+    let output = grep(&["-F", "a.b"], "a.b\naxb\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "a.b\n");
+    assert_eq!(grep(&["-F", "a.b"], "axb\n").status.code(), Some(1));
+}
+
+#[test]
+fn context_lines_around_matches() {
+    let input = "1\nmatch a\n3\n4\n5\nmatch b\n7\n";
+    let after = grep(&["-A", "1", "match"], input);
+    assert_eq!(stdout(&after), "match a\n3\n--\nmatch b\n7\n");
+    let before = grep(&["-B", "1", "match"], input);
+    assert_eq!(stdout(&before), "1\nmatch a\n--\n5\nmatch b\n");
+    // Groups that touch are printed together, without a separator
+    let both = grep(&["-C", "1", "match"], "match a\nx\nmatch b\n");
+    assert_eq!(stdout(&both), "match a\nx\nmatch b\n");
+}
+
+#[test]
+fn max_count_stops_after_that_many_lines() {
+    let output = grep(&["-m", "2", "hit"], "hit 1\nhit 2\nhit 3\nhit 4\nhit 5\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "hit 1\nhit 2\n");
+}
+
+#[test]
+fn list_files_with_and_without_matches() {
+    let dir = temp_dir("list-files");
+    fs::write(dir.join("hit.txt"), "needle\n").unwrap();
+    fs::write(dir.join("miss.txt"), "hay\n").unwrap();
+    let with = grep_in(&dir, &["-l", "needle", "hit.txt", "miss.txt"], "");
+    assert_eq!(with.status.code(), Some(0));
+    assert_eq!(stdout(&with), "hit.txt\n");
+    let without = grep_in(&dir, &["-L", "needle", "hit.txt", "miss.txt"], "");
+    assert_eq!(stdout(&without), "miss.txt\n");
+}
+
+#[test]
+fn quiet_prints_nothing_and_sets_the_status() {
+    let hit = grep(&["-q", "needle"], "hay\nneedle\n");
+    assert_eq!(hit.status.code(), Some(0));
+    assert_eq!(stdout(&hit), "");
+    let miss = grep(&["-q", "needle"], "hay\n");
+    assert_eq!(miss.status.code(), Some(1));
+    assert_eq!(stdout(&miss), "");
+}
+
+#[test]
+fn count_prints_the_number_of_selected_lines() {
+    let input = "hit\nmiss\nhit\nhit\n";
+    assert_eq!(stdout(&grep(&["-c", "hit"], input)), "3\n");
+    assert_eq!(stdout(&grep(&["-cv", "hit"], input)), "1\n");
+    assert_eq!(stdout(&grep(&["-cm", "2", "hit"], input)), "2\n");
+    let none = grep(&["-c", "hit"], "miss\n");
+    assert_eq!(none.status.code(), Some(1));
+    assert_eq!(stdout(&none), "0\n");
+}
+
+#[test]
+fn byte_offset_of_lines_and_matches() {
+    let input = "ab\ncd 12\nx34\n";
+    assert_eq!(
+        stdout(&grep(&["-b", "-E", r"\d"], input)),
+        "3:cd 12\n9:x34\n"
+    );
+    assert_eq!(
+        stdout(&grep(&["-ob", "-E", r"\d+"], input)),
+        "6:12\n10:34\n"
+    );
+}
+
+#[test]
+fn sed_replaces_on_every_line() {
+    let input = "foo foo\nbar\nfoofoo\n";
+    let global = grep(&["--sed", "s/foo/bar/g"], input);
+    assert_eq!(global.status.code(), Some(0));
+    assert_eq!(stdout(&global), "bar bar\nbar\nbarbar\n");
+    let first = grep(&["--sed", "s/foo/bar/"], input);
+    assert_eq!(stdout(&first), "bar foo\nbar\nbarfoo\n");
+}
+
+#[test]
+fn include_glob_limits_the_files_searched() {
+    let dir = temp_dir("include");
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(dir.join("src/main.rs"), "fn needle() {}\n").unwrap();
+    fs::write(dir.join("src/notes.txt"), "needle\n").unwrap();
+    fs::write(dir.join("lib.rs"), "// needle\n").unwrap();
+    let output = grep_in(&dir, &["-r", "--include=*.rs", "needle", "."], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "lib.rs:// needle\nsrc/main.rs:fn needle() {}\n"
+    );
+    let output = grep_in(&dir, &["-r", "--exclude=*.rs", "needle", "."], "");
+    assert_eq!(stdout(&output), "src/notes.txt:needle\n");
+}
+
+#[test]
+fn invalid_glob_is_an_error() {
+    for glob in ["[z-a]", "[]", "[!]", "[[:foo:]]"] {
+        let output = grep(&["-r", &format!("--include={}", glob), "x", "."], "");
+        assert_eq!(output.status.code(), Some(2), "{}", glob);
+    }
+}
+
+#[test]
+fn sed_takes_its_delimiter_from_the_command() {
+    let input = "PATH=/usr/bin:/usr/local/bin\n";
+    let output = grep(&["--sed", "s|/usr|/opt|"], input);
+    assert_eq!(stdout(&output), "PATH=/opt/bin:/usr/local/bin\n");
+    let escaped = grep(&["--sed", r"s/\/usr/\/opt/g"], input);
+    assert_eq!(stdout(&escaped), "PATH=/opt/bin:/opt/local/bin\n");
+}
+
+#[test]
+fn no_messages_hides_file_errors_but_keeps_the_status() {
+    let dir = temp_dir("no-messages");
+    let quiet = grep_in(&dir, &["-s", "x", "missing.txt"], "");
+    assert_eq!(quiet.status.code(), Some(2));
+    assert_eq!(stderr(&quiet), "");
+    let loud = grep_in(&dir, &["x", "missing.txt"], "");
+    assert_eq!(loud.status.code(), Some(2));
+    assert!(stderr(&loud).starts_with("missing.txt: "));
+}
+
+#[test]
+fn color_always_highlights_the_match() {
+    let output = grep(&["--color=always", "cat"], "a cat\n");
+    assert_eq!(stdout(&output), "a \x1b[01;31mcat\x1b[m\n");
+    let many = grep(&["--color=always", "cat"], "cat and cat\n");
+    assert_eq!(
+        stdout(&many),
+        "\x1b[01;31mcat\x1b[m and \x1b[01;31mcat\x1b[m\n"
+    );
+    let only = grep(&["--color=always", "-o", "cat"], "cat and cat\n");
+    assert_eq!(
+        stdout(&only),
+        "\x1b[01;31mcat\x1b[m\n\x1b[01;31mcat\x1b[m\n"
+    );
+    let never = grep(&["--color=never", "cat"], "a cat\n");
+    assert_eq!(stdout(&never), "a cat\n");
+    // Output to a pipe isn't a terminal, so it is never colored by default
+    assert_eq!(stdout(&grep(&["cat"], "a cat\n")), "a cat\n");
+    assert_eq!(stdout(&grep(&["--color", "cat"], "a cat\n")), "a cat\n");
+}
+
+#[test]
+fn unknown_option_is_a_usage_error() {
+    let output = grep(&["-y", "x"], "x\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stdout(&output), "");
+    assert!(stderr(&output).contains("unknown option '-y'"));
+    assert_eq!(grep(&["--no-such-flag", "x"], "x\n").status.code(), Some(2));
+}
+
+#[test]
+fn windows_line_endings_match_and_print_unchanged() {
+    let output = grep(&["foo$"], "foo\r\nbar\r\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "foo\r\n");
+    assert_eq!(stdout(&grep(&["-x", "bar"], "foo\r\nbar\r\n")), "bar\r\n");
+}
+
+#[test]
+fn double_dash_ends_options() {
+    let output = grep(&["--", "-v"], "a\n-v\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "-v\n");
+    assert_eq!(stdout(&grep(&["-c", "--", "-cv"], "-cv\n-c\n")), "1\n");
+}